@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use wasm_bindgen::{prelude::wasm_bindgen};
 
 use crate::emulator::reg_file::{LR, PC, SP};
+use crate::emulator::sys_reg::SysReg;
 
 use self::{
     preprocess::{expand_ite, expand_push_pop},
@@ -14,6 +15,8 @@ use super::*;
 pub enum AssemblerIntermediary {
     Assembled(u64),
     Original(String),
+    // a word holding the address of a label, resolved once labels are known
+    Label(String),
 }
 
 pub fn assemble_raw(lines: Vec<String>) -> Vec<AssemblerIntermediary> {
@@ -52,6 +55,19 @@ pub fn assemble_raw(lines: Vec<String>) -> Vec<AssemblerIntermediary> {
                 let num = line.parse::<i64>().unwrap();
                 ret.push(AssemblerIntermediary::Assembled(num as u64));
             }
+        } else if line.starts_with(".vectors") {
+            // .vectors
+            // 0, div_zero, 0, timer
+            // one word per entry, 0 leaves the vector missing
+            let line = it.next().unwrap_or_else(|| {
+                panic!("Missing entries after .vectors");
+            });
+            for entry in line.split(',').map(|entry| entry.trim()) {
+                match entry.parse::<u64>() {
+                    Ok(num) => ret.push(AssemblerIntermediary::Assembled(num)),
+                    Err(_) => ret.push(AssemblerIntermediary::Label(entry.to_string())),
+                }
+            }
         } else {
             ret.push(AssemblerIntermediary::Original(line.clone()));
         }
@@ -82,6 +98,10 @@ pub fn generate_label_map(
                 ret.push(AssemblerIntermediary::Assembled(*v));
                 line_count += 1;
             }
+            AssemblerIntermediary::Label(label) => {
+                ret.push(AssemblerIntermediary::Label(label.clone()));
+                line_count += 1;
+            }
         }
     }
     (label_map, ret)
@@ -128,17 +148,39 @@ pub fn parse_cond(postfix: &str) -> u64 {
 pub fn parse_instruction(l: &str) -> (u64, bool, u64, &str) {
     let parts = l.split_whitespace().collect::<Vec<&str>>();
     let to_parse = parts[0];
-    let mut postfix = "";
+    let postfix: &str;
     let opcode: u64;
     let mut set_flags = false;
     let mut cond_code = 0b1110 as u64;
     let op_name: &str;
     if to_parse.starts_with("nop") {
         opcode = 0b00_0000_0000_0000;
+        postfix = &to_parse[3..];
         op_name = "nop";
     } else if to_parse.starts_with("hlt") {
         opcode = 0b00_0000_0000_0001;
+        postfix = &to_parse[3..];
         op_name = "hlt";
+    } else if to_parse.starts_with("msr") {
+        opcode = 0b00_0000_0001_0000;
+        postfix = &to_parse[3..];
+        op_name = "msr";
+    } else if to_parse.starts_with("mrs") {
+        opcode = 0b00_0000_0001_0001;
+        postfix = &to_parse[3..];
+        op_name = "mrs";
+    } else if to_parse.starts_with("cpsie") {
+        opcode = 0b00_0000_0010_0000;
+        postfix = &to_parse[5..];
+        op_name = "cpsie";
+    } else if to_parse.starts_with("cpsid") {
+        opcode = 0b00_0000_0010_0001;
+        postfix = &to_parse[5..];
+        op_name = "cpsid";
+    } else if to_parse.starts_with("eret") {
+        opcode = 0b00_0000_0010_0010;
+        postfix = &to_parse[4..];
+        op_name = "eret";
     } else if to_parse.starts_with("mov") {
         opcode = 0b01_0000_0000_0000;
         postfix = &to_parse[3..];
@@ -259,6 +301,10 @@ pub fn parse_instruction(l: &str) -> (u64, bool, u64, &str) {
         opcode = 0b11_0000_0000_0000;
         postfix = &to_parse[1..];
         op_name = "b";
+    } else if to_parse.starts_with("svc") {
+        opcode = 0b01_0000_0011_0011;
+        postfix = &to_parse[3..];
+        op_name = "svc";
     } else if to_parse.starts_with("int") {
         opcode = 0b01_0000_0011_0010;
         postfix = &to_parse[3..];
@@ -267,6 +313,15 @@ pub fn parse_instruction(l: &str) -> (u64, bool, u64, &str) {
         opcode = 0b01_0000_0011_0001;
         postfix = &to_parse[3..];
         op_name = "qry";
+    } else if to_parse.starts_with("in") {
+        // after int, which starts the same
+        opcode = 0b00_0000_0011_0000;
+        postfix = &to_parse[2..];
+        op_name = "in";
+    } else if to_parse.starts_with("out") {
+        opcode = 0b00_0000_0011_0001;
+        postfix = &to_parse[3..];
+        op_name = "out";
     }
     else {
         panic!("Unknown instruction: {}", to_parse);
@@ -279,19 +334,30 @@ pub fn parse_instruction(l: &str) -> (u64, bool, u64, &str) {
     (opcode, set_flags, cond_code, op_name)
 }
 
-const NO_OPERANDS: [&str; 2] = ["nop", "hlt"];
+const NO_OPERANDS: [&str; 5] = ["nop", "hlt", "cpsie", "cpsid", "eret"];
 const D_OPERAND: [&str; 3] = ["mvi", "pop", "push"];
-const C_OPERAND: [&str; 3] = ["b", "bl", "qry"];
+const C_OPERAND: [&str; 4] = ["b", "bl", "qry", "svc"];
+const C_B_OPERAND: [&str; 1] = ["out"];
 const B_C_OPERAND: [&str; 5] = ["cmp", "cmn", "tst", "teq", "int"];
-const D_C_OPERAND: [&str; 2] = ["mov", "mvn"];
+const D_C_OPERAND: [&str; 3] = ["mov", "mvn", "in"];
 const D_B_C_OPERAND: [&str; 16] = [
     "add", "sub", "mul", "div", "smul", "sdiv", "modu", "smodu", "and", "orr", "eor", "lsl", "lsr",
     "asr", "rol", "ror",
 ];
 const D_A_B_C_OPERAND: [&str; 2] = ["str", "ldr"];
 
+// system registers are only named by msr and mrs
+pub fn parse_sys_reg(operand: &str) -> u64 {
+    match SysReg::from_name(operand) {
+        Some(sys_reg) => sys_reg as u64,
+        None => panic!("Unknown system register: {}", operand),
+    }
+}
+
 pub fn parse_operand(operand: &str, label_map: &HashMap<String, usize>) -> (u64, bool) {
-    if operand.starts_with("lr") {
+    if SysReg::from_name(operand).is_some() {
+        panic!("System register outside msr or mrs: {}", operand);
+    } else if operand.starts_with("lr") {
         (LR as u64, false)
     } else if operand.starts_with("sp") {
         (SP as u64, false)
@@ -335,6 +401,21 @@ pub fn operand_to_u64(l: &str, op_name: &str, label_map: HashMap<String, usize>)
         let splitted = split_operands(l);
         let (rc, is_imm) = parse_operand(splitted[0], &label_map);
         (rc, is_imm)
+    } else if op_name == "msr" {
+        let splitted = split_operands(l);
+        let rc = parse_sys_reg(splitted[0]);
+        let (rb, _) = parse_operand(splitted[1], &label_map);
+        (rb << 32 | rc, true)
+    } else if op_name == "mrs" {
+        let splitted = split_operands(l);
+        let (rd, _) = parse_operand(splitted[0], &label_map);
+        let rc = parse_sys_reg(splitted[1]);
+        (rd << 40 | rc, true)
+    } else if C_B_OPERAND.contains(&op_name) {
+        let splitted = split_operands(l);
+        let (rc, is_imm) = parse_operand(splitted[0], &label_map);
+        let (rb, _) = parse_operand(splitted[1], &label_map);
+        (rb << 32 | rc, is_imm)
     } else if B_C_OPERAND.contains(&op_name) {
         let splitted = split_operands(l);
         let (rb, _) = parse_operand(splitted[0], &label_map);
@@ -381,6 +462,12 @@ pub fn to_binary(lines: &Vec<String>) -> Vec<u64> {
             AssemblerIntermediary::Assembled(v) => {
                 ret.push(v);
             }
+            AssemblerIntermediary::Label(label) => {
+                let addr = label_map.get(&label).unwrap_or_else(|| {
+                    panic!("Unknown label: {}", label);
+                });
+                ret.push(*addr as u64);
+            }
             AssemblerIntermediary::Original(s) => {
                 let (opcode, set_flags, cond_code, op_name) = parse_instruction(&s);
                 let (operand, is_imm) = operand_to_u64(&s, op_name, label_map.clone());
@@ -1,18 +1,13 @@
 // expand push r0, r1, r2 to push r0, push r1, push r2, also the pop
+// a condition stays on every expanded instruction
 pub fn expand_push_pop(lines: &Vec<String>) -> Vec<String> {
     let mut ret = Vec::new();
     for line in lines {
-        if line.starts_with("push") {
+        if line.starts_with("push") || line.starts_with("pop") {
             let mut regs = line.split_whitespace().collect::<Vec<&str>>();
-            regs.remove(0);
+            let op = regs.remove(0);
             for reg in regs {
-                ret.push(format!("push {}", reg.trim_end_matches(',')));
-            }
-        } else if line.starts_with("pop") {
-            let mut regs = line.split_whitespace().collect::<Vec<&str>>();
-            regs.remove(0);
-            for reg in regs {
-                ret.push(format!("pop {}", reg.trim_end_matches(',')));
+                ret.push(format!("{} {}", op, reg.trim_end_matches(',')));
             }
         } else {
             ret.push(line.clone());
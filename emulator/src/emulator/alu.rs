@@ -2,6 +2,19 @@ use super::{cpsr::Cpsr, instr::Operation};
 
 pub struct Alu {}
 
+// shifts of 64 or more move every bit out
+fn shl(val: u64, by: u64) -> u64 {
+    val.checked_shl(by.min(64) as u32).unwrap_or(0)
+}
+fn shr(val: u64, by: u64) -> u64 {
+    val.checked_shr(by.min(64) as u32).unwrap_or(0)
+}
+// the sign fills every bit
+fn asr(val: u64, by: u64) -> u64 {
+    let val = val as i64;
+    val.checked_shr(by.min(64) as u32).unwrap_or(val >> 63) as u64
+}
+
 impl Alu {
     pub fn new() -> Alu {
         Alu {}
@@ -91,7 +104,7 @@ impl Alu {
             }
             Operation::Mul => {
                 let result = b.wrapping_mul(c);
-                let carry = if b > u64::MAX / c { 1 } else { 0 };
+                let carry = if b.checked_mul(c).is_none() { 1 } else { 0 };
                 (
                     result,
                     Cpsr {
@@ -160,7 +173,7 @@ impl Alu {
                         1,
                     )
                 }
-                let result = (b as i64 / c as i64) as u64;
+                let result = (b as i64).wrapping_div(c as i64) as u64;
                 (
                     result,
                     Cpsr {
@@ -214,7 +227,7 @@ impl Alu {
                         1,
                     )
                 }
-                let result = (b as i64 % c as i64) as u64;
+                let result = (b as i64).wrapping_rem(c as i64) as u64;
                 (
                     result,
                     Cpsr {
@@ -312,42 +325,42 @@ impl Alu {
                 )
             }
             Operation::Lsl => {
-                let result = b << c;
+                let result = shl(b, c);
                 (
                     result,
                     Cpsr {
                         n: ((result >> 63) & 1) == 1,
                         z: result == 0,
                         v: false,
-                        c: ((b << (c - 1)) & 0x8000000000000000) != 0,
+                        c: c != 0 && (shl(b, c - 1) & 0x8000000000000000) != 0,
                     }
                     .to_u8(),
                     0
                 )
             }
             Operation::Lsr => {
-                let result = b >> c;
+                let result = shr(b, c);
                 (
                     result,
                     Cpsr {
                         n: ((result >> 63) & 1) == 1,
                         z: result == 0,
                         v: false,
-                        c: ((b >> (c - 1)) & 1) == 1,
+                        c: c != 0 && (shr(b, c - 1) & 1) == 1,
                     }
                     .to_u8(),
                     0
                 )
             }
             Operation::Asr => {
-                let result = (b as i64 >> c) as u64;
+                let result = asr(b, c);
                 (
                     result,
                     Cpsr {
                         n: ((result >> 63) & 1) == 1,
                         z: result == 0,
                         v: false,
-                        c: ((b as i64 >> (c - 1)) & 1) == 1,
+                        c: c != 0 && (asr(b, c - 1) & 1) == 1,
                     }
                     .to_u8(),
                     0
@@ -362,7 +375,7 @@ impl Alu {
                         n: ((result >> 63) & 1) == 1,
                         z: result == 0,
                         v: false,
-                        c: ((b.rotate_left(c - 1)) & 1) == 1,
+                        c: ((b.rotate_left(c.wrapping_sub(1))) & 1) == 1,
                     }
                     .to_u8(),
                     0
@@ -377,7 +390,7 @@ impl Alu {
                         n: ((result >> 63) & 1) == 1,
                         z: result == 0,
                         v: false,
-                        c: ((b.rotate_right(c - 1)) & 1) == 1,
+                        c: ((b.rotate_right(c.wrapping_sub(1))) & 1) == 1,
                     }
                     .to_u8(),
                     0
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+use super::device::Device;
+use super::fault::Fault;
+use super::rtc::RTC_CYCLES_PER_SECOND;
+
+pub const AUDIO_SAMPLE_RATE: u64 = 8000;
+// the highest tone the sample rate can carry, higher frequencies are clamped to it
+pub const AUDIO_MAX_FREQ: u64 = AUDIO_SAMPLE_RATE / 2;
+pub const AUDIO_CYCLES_PER_SAMPLE: u64 = RTC_CYCLES_PER_SECOND / AUDIO_SAMPLE_RATE;
+pub const AUDIO_CHANNELS: u64 = 2;
+// pcm samples past this are dropped
+pub const AUDIO_FIFO_SIZE: usize = 256;
+
+// mmio registers
+// writing pcm queues a signed 16 bit sample, fifo is the number queued
+pub const AUDIO_PCM: u64 = 0x00;
+pub const AUDIO_FIFO: u64 = 0x08;
+// tone channels, each with a frequency in hz, duty out of 256 and volume out of 255
+pub const AUDIO_TONE: u64 = 0x10;
+pub const AUDIO_TONE_FREQ: u64 = 0x00;
+pub const AUDIO_TONE_DUTY: u64 = 0x08;
+pub const AUDIO_TONE_VOLUME: u64 = 0x10;
+pub const AUDIO_TONE_SIZE: u64 = 0x18;
+pub const AUDIO_SIZE: u64 = AUDIO_TONE + AUDIO_CHANNELS * AUDIO_TONE_SIZE;
+
+#[derive(Clone, Copy, Default)]
+struct Tone {
+    freq: u64,
+    duty: u64,
+    volume: u64,
+    // position in the period, in 1 / AUDIO_SAMPLE_RATE of a period
+    phase: u64,
+}
+
+impl Tone {
+    fn sample(&mut self) -> f32 {
+        if self.freq == 0 || self.volume == 0 {
+            return 0.0;
+        }
+        let high = self.phase * 256 < self.duty * AUDIO_SAMPLE_RATE;
+        self.phase = (self.phase + self.freq) % AUDIO_SAMPLE_RATE;
+        let level = self.volume as f32 / 255.0;
+        if high {
+            level
+        } else {
+            -level
+        }
+    }
+}
+
+// renders a sample every AUDIO_CYCLES_PER_SAMPLE cycles by mixing the tones and the next pcm sample
+// the host pulls the rendered samples, in the range -1 to 1
+pub struct Audio {
+    tones: Vec<Tone>,
+    fifo: VecDeque<i16>,
+    cycles: u64,
+    samples: Vec<f32>,
+}
+
+impl Audio {
+    pub fn new() -> Audio {
+        Audio {
+            tones: vec![Tone::default(); AUDIO_CHANNELS as usize],
+            fifo: VecDeque::new(),
+            cycles: 0,
+            samples: Vec::new(),
+        }
+    }
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+    fn render(&mut self) -> f32 {
+        let tones: f32 = self.tones.iter_mut().map(|tone| tone.sample()).sum();
+        let pcm = self.fifo.pop_front().map(|sample| sample as f32 / 32768.0).unwrap_or(0.0);
+        (tones + pcm).clamp(-1.0, 1.0)
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio::new()
+    }
+}
+
+impl Device for Audio {
+    fn size(&self) -> u64 {
+        AUDIO_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            AUDIO_FIFO => Ok(self.fifo.len() as u64),
+            _ if (AUDIO_TONE..AUDIO_SIZE).contains(&offset) => {
+                let tone = &self.tones[((offset - AUDIO_TONE) / AUDIO_TONE_SIZE) as usize];
+                match (offset - AUDIO_TONE) % AUDIO_TONE_SIZE {
+                    AUDIO_TONE_FREQ => Ok(tone.freq),
+                    AUDIO_TONE_DUTY => Ok(tone.duty),
+                    _ => Ok(tone.volume),
+                }
+            }
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            AUDIO_PCM => {
+                if self.fifo.len() < AUDIO_FIFO_SIZE {
+                    self.fifo.push_back(val as i16);
+                }
+            }
+            _ if (AUDIO_TONE..AUDIO_SIZE).contains(&offset) => {
+                let tone = &mut self.tones[((offset - AUDIO_TONE) / AUDIO_TONE_SIZE) as usize];
+                match (offset - AUDIO_TONE) % AUDIO_TONE_SIZE {
+                    AUDIO_TONE_FREQ => tone.freq = val.min(AUDIO_MAX_FREQ),
+                    AUDIO_TONE_DUTY => tone.duty = val.min(256),
+                    _ => tone.volume = val.min(255),
+                }
+            }
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    fn tick(&mut self) {
+        self.cycles += 1;
+        if self.cycles == AUDIO_CYCLES_PER_SAMPLE {
+            self.cycles = 0;
+            let sample = self.render();
+            self.samples.push(sample);
+        }
+    }
+}
+
+// mono 16 bit pcm wav at AUDIO_SAMPLE_RATE
+pub fn wav(samples: &[f32]) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut ret = Vec::with_capacity(44 + data_len as usize);
+    ret.extend_from_slice(b"RIFF");
+    ret.extend_from_slice(&(36 + data_len).to_le_bytes());
+    ret.extend_from_slice(b"WAVEfmt ");
+    ret.extend_from_slice(&16u32.to_le_bytes());
+    // pcm, one channel
+    ret.extend_from_slice(&1u16.to_le_bytes());
+    ret.extend_from_slice(&1u16.to_le_bytes());
+    ret.extend_from_slice(&(AUDIO_SAMPLE_RATE as u32).to_le_bytes());
+    ret.extend_from_slice(&(AUDIO_SAMPLE_RATE as u32 * 2).to_le_bytes());
+    ret.extend_from_slice(&2u16.to_le_bytes());
+    ret.extend_from_slice(&16u16.to_le_bytes());
+    ret.extend_from_slice(b"data");
+    ret.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        ret.extend_from_slice(&((sample * 32767.0) as i16).to_le_bytes());
+    }
+    ret
+}
@@ -0,0 +1,152 @@
+use super::device::Device;
+use super::fault::Fault;
+use super::mem::WORD_SIZE;
+
+pub const SECTOR_SIZE: u64 = 512;
+// cycles from a command to its completion
+pub const BLK_LATENCY: u64 = 8;
+
+// mmio registers, count is the number of sectors in the image
+pub const BLK_CMD: u64 = 0x00;
+pub const BLK_STATUS: u64 = 0x08;
+pub const BLK_SECTOR: u64 = 0x10;
+pub const BLK_COUNT: u64 = 0x18;
+pub const BLK_CTRL: u64 = 0x20;
+// one sector, big endian words like the rest of memory
+pub const BLK_BUFFER: u64 = 0x200;
+pub const BLK_SIZE: u64 = BLK_BUFFER + SECTOR_SIZE;
+
+// commands
+pub const BLK_CMD_READ: u64 = 1;
+pub const BLK_CMD_WRITE: u64 = 2;
+
+// status bits, write done or error to clear them
+pub const BLK_BUSY: u64 = 0b001;
+pub const BLK_DONE: u64 = 0b010;
+pub const BLK_ERROR: u64 = 0b100;
+
+// control bits
+pub const BLK_IRQ_ENABLE: u64 = 0b1;
+
+// a disk of sectors moved to and from the buffer by commands
+// a command completes after a fixed latency and then raises the interrupt
+pub struct BlockDevice {
+    irq: u64,
+    ctrl: u64,
+    status: u64,
+    sector: u64,
+    cmd: u64,
+    wait: u64,
+    completed: bool,
+    buffer: Vec<u8>,
+    image: Vec<u8>,
+}
+
+impl BlockDevice {
+    // the image is padded with zeros to a whole number of sectors
+    pub fn new(irq: u64, mut image: Vec<u8>) -> BlockDevice {
+        let len = (image.len() as u64).div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+        image.resize(len as usize, 0);
+        BlockDevice {
+            irq,
+            ctrl: 0,
+            status: 0,
+            sector: 0,
+            cmd: 0,
+            wait: 0,
+            completed: false,
+            buffer: vec![0; SECTOR_SIZE as usize],
+            image,
+        }
+    }
+    pub fn image(&self) -> &[u8] {
+        &self.image
+    }
+    pub fn sectors(&self) -> u64 {
+        self.image.len() as u64 / SECTOR_SIZE
+    }
+    fn run(&mut self) {
+        self.status &= !BLK_BUSY;
+        if self.sector >= self.sectors() {
+            self.status |= BLK_ERROR;
+            return;
+        }
+        let start = (self.sector * SECTOR_SIZE) as usize;
+        let sector = &mut self.image[start..start + SECTOR_SIZE as usize];
+        match self.cmd {
+            BLK_CMD_READ => self.buffer.copy_from_slice(sector),
+            BLK_CMD_WRITE => sector.copy_from_slice(&self.buffer),
+            _ => unreachable!(),
+        }
+        self.status |= BLK_DONE;
+    }
+    // the word of the sector buffer at offset, which has to be a whole word inside it
+    fn buffer_word(&mut self, offset: u64) -> Result<&mut [u8], Fault> {
+        if !offset.is_multiple_of(WORD_SIZE) {
+            return Err(Fault::UnalignedAccess);
+        }
+        let start = (offset - BLK_BUFFER) as usize;
+        self.buffer.get_mut(start..start + WORD_SIZE as usize).ok_or(Fault::BusError)
+    }
+}
+
+impl Device for BlockDevice {
+    fn size(&self) -> u64 {
+        BLK_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            BLK_CMD => Ok(self.cmd),
+            BLK_STATUS => Ok(self.status),
+            BLK_SECTOR => Ok(self.sector),
+            BLK_COUNT => Ok(self.sectors()),
+            BLK_CTRL => Ok(self.ctrl),
+            _ if offset >= BLK_BUFFER => {
+                let word = self.buffer_word(offset)?;
+                Ok(word.iter().fold(0, |val, byte| val << 8 | *byte as u64))
+            }
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            // commands while busy or unknown commands are errors
+            BLK_CMD => {
+                if self.status & BLK_BUSY != 0 || !matches!(val, BLK_CMD_READ | BLK_CMD_WRITE) {
+                    self.status |= BLK_ERROR;
+                } else {
+                    self.cmd = val;
+                    self.wait = BLK_LATENCY;
+                    self.status = BLK_BUSY;
+                }
+            }
+            BLK_STATUS => self.status &= !(val & (BLK_DONE | BLK_ERROR)),
+            BLK_SECTOR => self.sector = val,
+            BLK_CTRL => self.ctrl = val,
+            _ if offset >= BLK_BUFFER => {
+                self.buffer_word(offset)?.copy_from_slice(&val.to_be_bytes());
+            }
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    fn tick(&mut self) {
+        if self.status & BLK_BUSY == 0 {
+            return;
+        }
+        self.wait -= 1;
+        if self.wait == 0 {
+            self.run();
+            self.completed = self.ctrl & BLK_IRQ_ENABLE != 0;
+        }
+    }
+    // the interrupt data is the status after the command
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if self.completed {
+            self.completed = false;
+            Some((self.irq, self.status))
+        } else {
+            None
+        }
+    }
+}
@@ -1,10 +1,34 @@
+use std::mem;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, io, path::Path};
+
 use wasm_bindgen::prelude::*;
 use super::alu::Alu;
+use super::audio::Audio;
+use super::block::BlockDevice;
 use super::decoder::Decoder;
+use super::device::{AttachError, AttachedDevice, Device, Signal};
+use super::dma::Dma;
+use super::fault::{Exception, Fault, StepError};
+use super::framebuffer::Framebuffer;
+use super::gpio::Gpio;
+use super::host_port::HostPorts;
 use super::mem_addr_calculator::MemAddressCalculator;
 use super::wire::{SingleWire, Wire};
-use super::mem::Mem;
+use super::mem::{Mem, MemError, WORD_SIZE};
+use super::mem_map::{Access, Region, RegionKind, PERM_R, PERM_W};
+use super::mmu::{Mmu, MMUCTL_ENABLE};
+use super::nic::Nic;
+use super::rng::Rng;
+use super::rtc::Rtc;
+use super::sys_reg::SysReg;
+use super::text_display::TextDisplay;
+use super::timer::Timer;
+use super::uart::Uart;
+use super::watchdog::Watchdog;
 use super::instr::*;
+use super::int_ctrl::{is_irq, IntCtrl, INT_CTRL_SIZE};
+use super::keyboard::{key_event, Keyboard};
 use super::reg_file::*;
 
 pub const MEM_SIZE: usize = 4 * 1024;
@@ -36,17 +60,53 @@ pub struct CoreSys {
     pub query: Wire,
 
     int_table: Vec<u64>,
+    // vector table in guest memory, int_table is used until it is set
+    vbar: Option<u64>,
+    fault: Option<Fault>,
+    fault_pc: u64,
+    fault_instr: u64,
+    fault_addr: u64,
+    // a non maskable interrupt waiting to be taken, with its data
+    nmi: Option<u64>,
+    resets: u64,
     memory: Mem,
+    mmu: Mmu,
+    devices: Vec<AttachedDevice>,
+    bus_owner: u64,
+    int_ctrl: IntCtrl,
     decoder: Decoder,
     alu: Alu,
     mem_cal: MemAddressCalculator,
     reg_file: RegFile,
 }
 
+// a device can only be wired to a line the interrupt controller can raise
+fn check_irq(irq: u64) -> Result<(), AttachError> {
+    if is_irq(irq) {
+        Ok(())
+    } else {
+        Err(AttachError::ReservedIrq)
+    }
+}
+
 #[wasm_bindgen]
 impl CoreSys {
     #[wasm_bindgen(constructor)]
     pub fn new() -> CoreSys {
+        CoreSys::with_mem(Mem::new(vec![0; MEM_SIZE]))
+    }
+    // the size is a whole number of words, up to MAX_FLAT_SIZE
+    pub fn with_memory(size: u64) -> Result<CoreSys, MemError> {
+        Ok(CoreSys::with_mem(Mem::flat(size)?))
+    }
+    // pages are only allocated when touched, for large 64-bit address spaces
+    pub fn with_sparse_memory(size: u64) -> Result<CoreSys, MemError> {
+        Ok(CoreSys::with_mem(Mem::sparse(size)?))
+    }
+}
+
+impl CoreSys {
+    fn with_mem(memory: Mem) -> CoreSys {
         let mut ret = CoreSys {
             op: Wire::new(),
             cond: Wire::new(),
@@ -73,7 +133,18 @@ impl CoreSys {
             query: Wire::new(),
 
             int_table: Vec::new(),
-            memory: Mem::new(vec![0; MEM_SIZE]),
+            vbar: None,
+            fault: None,
+            fault_pc: 0,
+            fault_instr: 0,
+            fault_addr: 0,
+            nmi: None,
+            resets: 0,
+            memory,
+            mmu: Mmu::new(),
+            devices: Vec::new(),
+            bus_owner: 0,
+            int_ctrl: IntCtrl::new(),
             decoder: Decoder::new(),
             alu: Alu::new(),
             mem_cal: MemAddressCalculator::new(),
@@ -86,9 +157,32 @@ impl CoreSys {
 
 #[wasm_bindgen]
 impl CoreSys {
+    // empty for sparse memory, use dump_mem_pages or dump_mem_range
     pub fn dump_mem(&self) -> Vec<u8> {
         self.memory.dump()
     }
+    // every allocated page as its number in 8 big endian bytes followed by its contents
+    pub fn dump_mem_pages(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        for (page, contents) in self.memory.pages() {
+            ret.extend_from_slice(&page.to_be_bytes());
+            ret.extend_from_slice(contents);
+        }
+        ret
+    }
+    pub fn dump_mem_range(&self, addr: u64, len: u64) -> Vec<u8> {
+        self.memory.dump_range(addr, len)
+    }
+    pub fn get_mem_size(&self) -> u64 {
+        self.memory.get_size()
+    }
+    pub fn get_mem_pages(&self) -> u64 {
+        self.memory.allocated_pages()
+    }
+    // base, size, kind, perms for each mapped region
+    pub fn dump_regions(&self) -> Vec<u64> {
+        self.memory.dump_regions()
+    }
     pub fn dump_common_regs(&self) -> Vec<u64> {
         self.reg_file.dump_common()
     }
@@ -100,20 +194,231 @@ impl CoreSys {
 #[wasm_bindgen]
 impl CoreSys {
     pub fn fetch(mut self) -> CoreSys {
-        self.instr = self.instr.set(
-            self.memory.get_word(
-                self.reg_file.get_pc()
-            )
-        );
+        let pc = self.reg_file.get_pc();
+        self.pc_mem = self.pc_mem.set(pc);
+        match self.read_word(pc, Access::Execute) {
+            Ok(instr) => {
+                self.instr = self.instr.set(instr);
+            }
+            Err(fault) => {
+                self.instr = self.instr.set(0);
+                return self.raise_at(fault, pc);
+            }
+        }
         self.reg_file = self.reg_file.next_pc();
         self
     }
+    pub fn map_region(mut self, base: u64, size: u64, kind: RegionKind, perms: u8) -> CoreSys {
+        self.memory.map(Region::new(base, size, kind, perms));
+        self
+    }
+    pub fn enable_mmu(mut self, ptbr: u64) -> CoreSys {
+        self.mmu.set_ptbr(ptbr);
+        self.mmu.set_ctl(MMUCTL_ENABLE);
+        self
+    }
+    pub fn disable_mmu(mut self) -> CoreSys {
+        self.mmu.set_ctl(0);
+        self
+    }
+    // the device attached at base also answers in and out from port on
+    // ignored when no device is attached at base
+    pub fn map_ports(mut self, base: u64, port: u64) -> CoreSys {
+        if let Some(attached) = self.devices.iter_mut().find(|attached| attached.base == Some(base)) {
+            attached.port = Some(port);
+        }
+        self
+    }
+    // count io ports from port on answered by the host, for hosts that cannot build a device
+    // in reads what set_host_port left there, out is queued for take_host_port_writes
+    pub fn attach_host_ports(&mut self, port: u64, count: u64) -> Result<(), AttachError> {
+        if count == 0 {
+            return Err(AttachError::Empty);
+        }
+        let device = Box::new(HostPorts::new(port, count));
+        self.devices.push(AttachedDevice { base: None, port: Some(port), device });
+        Ok(())
+    }
+    pub fn set_host_port(&mut self, port: u64, val: u64) {
+        if let Some(ports) = self.device::<HostPorts>() {
+            ports.set(port, val);
+        }
+    }
+    // port and value pairs of every out to the host ports since the last call, flattened
+    pub fn take_host_port_writes(&mut self) -> Vec<u64> {
+        self.device::<HostPorts>()
+            .map(|ports| ports.take_writes())
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|(port, val)| vec![port, val])
+            .collect()
+    }
+    pub fn map_int_ctrl(mut self, base: u64) -> CoreSys {
+        self.int_ctrl.map(base);
+        self.memory.map(Region::new(base, INT_CTRL_SIZE, RegionKind::Mmio, PERM_R | PERM_W));
+        self
+    }
+    pub fn attach_timer(&mut self, base: u64, irq: u64) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        self.attach(base, Box::new(Timer::new(irq)))
+    }
+    pub fn attach_uart(&mut self, base: u64, irq: u64) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        self.attach(base, Box::new(Uart::new(irq)))
+    }
+    // host side of the console, input is dropped when no uart is attached
+    pub fn console_write_input(&mut self, input: &[u8]) {
+        if let Some(uart) = self.device::<Uart>() {
+            uart.write_input(input);
+        }
+    }
+    pub fn console_take_output(&mut self) -> Vec<u8> {
+        self.device::<Uart>().map(|uart| uart.take_output()).unwrap_or_default()
+    }
+    // a bad configuration leaves the machine as it was
+    pub fn attach_framebuffer(&mut self, base: u64, width: u64, height: u64, bpp: u64, irq: u64) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        let fb = Framebuffer::new(width, height, bpp, irq)?;
+        self.attach(base, Box::new(fb))
+    }
+    // the frame as rgba bytes, empty when no framebuffer is attached
+    pub fn framebuffer_rgba(&mut self) -> Vec<u8> {
+        self.device::<Framebuffer>().map(|fb| fb.rgba()).unwrap_or_default()
+    }
+    pub fn attach_text_display(&mut self, base: u64, cols: u64, rows: u64) -> Result<(), AttachError> {
+        let display = TextDisplay::new(cols, rows)?;
+        self.attach(base, Box::new(display))
+    }
+    // the screen as newline separated lines, empty when no display is attached
+    pub fn get_text_screen(&mut self) -> String {
+        self.device::<TextDisplay>().map(|display| display.lines().join("\n")).unwrap_or_default()
+    }
+    pub fn get_text_attrs(&mut self) -> Vec<u8> {
+        self.device::<TextDisplay>().map(|display| display.attrs()).unwrap_or_default()
+    }
+    // x, y
+    pub fn get_text_cursor(&mut self) -> Vec<u64> {
+        self.device::<TextDisplay>()
+            .map(|display| {
+                let (x, y) = display.cursor();
+                vec![x, y]
+            })
+            .unwrap_or_default()
+    }
+    pub fn attach_keyboard(&mut self, base: u64, irq: u64) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        self.attach(base, Box::new(Keyboard::new(irq)))
+    }
+    // queue a key press or release, dropped when no keyboard is attached
+    pub fn key_event(&mut self, code: u8, pressed: bool, modifiers: u8) {
+        if let Some(keyboard) = self.device::<Keyboard>() {
+            keyboard.push(key_event(code, pressed, modifiers));
+        }
+    }
+    pub fn attach_block_device(&mut self, base: u64, irq: u64, image: Vec<u8>) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        self.attach(base, Box::new(BlockDevice::new(irq, image)))
+    }
+    // the disk image with the guest's writes, empty when no disk is attached
+    pub fn get_block_image(&mut self) -> Vec<u8> {
+        self.device::<BlockDevice>().map(|disk| disk.image().to_vec()).unwrap_or_default()
+    }
+    pub fn attach_dma(&mut self, base: u64, irq: u64) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        self.attach(base, Box::new(Dma::new(irq)))
+    }
+    // src, dst, len, ctrl, status, empty when no dma is attached
+    pub fn get_dma_state(&mut self) -> Vec<u64> {
+        self.device::<Dma>().map(|dma| dma.dump()).unwrap_or_default()
+    }
+    // 0 for the cpu, otherwise the device that used the bus in the last step, counted from 1 in attach order
+    pub fn get_bus_owner(&self) -> u64 {
+        self.bus_owner
+    }
+    // seconds is the starting wall clock time, a fixed seed keeps runs deterministic
+    pub fn attach_rtc(&mut self, base: u64, irq: u64, seconds: u64) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        self.attach(base, Box::new(Rtc::new(irq, seconds)))
+    }
+    pub fn set_rtc_time(&mut self, seconds: u64) {
+        if let Some(rtc) = self.device::<Rtc>() {
+            rtc.set_time(seconds);
+        }
+    }
+    pub fn attach_rng(&mut self, base: u64, seed: u64) -> Result<(), AttachError> {
+        self.attach(base, Box::new(Rng::new(seed)))
+    }
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        if let Some(rng) = self.device::<Rng>() {
+            rng.set_seed(seed);
+        }
+    }
+    pub fn attach_gpio(&mut self, base: u64, irq: u64) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        self.attach(base, Box::new(Gpio::new(irq)))
+    }
+    // switches and buttons, ignored when no gpio is attached
+    pub fn set_gpio_input(&mut self, pin: u64, level: bool) {
+        if let Some(gpio) = self.device::<Gpio>() {
+            gpio.set_input(pin, level);
+        }
+    }
+    pub fn set_gpio_inputs(&mut self, input: u64) {
+        if let Some(gpio) = self.device::<Gpio>() {
+            gpio.set_inputs(input);
+        }
+    }
+    // leds, seven segment digits, inputs and latched edges
+    pub fn get_gpio_state(&mut self) -> Vec<u64> {
+        self.device::<Gpio>().map(|gpio| gpio.dump()).unwrap_or_default()
+    }
+    pub fn get_seven_segment(&mut self) -> Vec<u8> {
+        self.device::<Gpio>().map(|gpio| gpio.digits()).unwrap_or_default()
+    }
+    pub fn attach_audio(&mut self, base: u64) -> Result<(), AttachError> {
+        self.attach(base, Box::new(Audio::new()))
+    }
+    // samples rendered since the last call, empty when no audio device is attached
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.device::<Audio>().map(|audio| audio.take_samples()).unwrap_or_default()
+    }
+    pub fn attach_nic(&mut self, base: u64, mac: u64, irq: u64) -> Result<(), AttachError> {
+        check_irq(irq)?;
+        self.attach(base, Box::new(Nic::new(mac, irq)))
+    }
+    pub fn attach_watchdog(&mut self, base: u64) -> Result<(), AttachError> {
+        self.attach(base, Box::new(Watchdog::new()))
+    }
+    // ctrl, timeout, count, status, expirations, empty when no watchdog is attached
+    pub fn get_watchdog_state(&mut self) -> Vec<u64> {
+        self.device::<Watchdog>().map(|watchdog| watchdog.dump()).unwrap_or_default()
+    }
+    pub fn set_irq_enabled(mut self, line: u64, enabled: bool) -> CoreSys {
+        self.int_ctrl.set_enabled(line, enabled);
+        self
+    }
+    pub fn set_irq_priority(mut self, line: u64, priority: u64) -> CoreSys {
+        self.int_ctrl.set_priority(line, priority);
+        self
+    }
+    // host side vector table, for programs that do not set vbar
     pub fn set_int_table(mut self, table: Vec<u64>) -> CoreSys {
         self.int_table = table;
         self
     }
+    pub fn set_vbar(mut self, base: u64) -> CoreSys {
+        self.vbar = Some(base);
+        self
+    }
     pub fn decode(mut self) -> CoreSys {
         let instr = self.decoder.decode(self.instr.get());
+        let decoded_op = match Operation::try_new(instr.op_code) {
+            Ok(op) => op,
+            Err(fault) => return self.raise(fault),
+        };
+        if let Err(fault) = ConditionCode::try_from_u8(instr.cond_code as u8) {
+            return self.raise(fault);
+        }
         self.op = self.op.set(instr.op_code);
         self.cond = self.cond.set(instr.cond_code);
         self.r_d_mem = self.r_d_mem.set(instr.reg_d_mem);
@@ -122,25 +427,14 @@ impl CoreSys {
         self.r_c = self.r_c.set(instr.reg_c);
         self.r_c_imm = self.r_c_imm.set(instr.c_is_imm);
         self.write_flags = self.write_flags.set(instr.set_flags);
-        let decoded_op = Operation::new(instr.op_code);
-        if decoded_op == Operation::B {
-            self.r_d_mem = self.r_d_mem.set(
-                PC as u64
-            )
-        } else if decoded_op == Operation::Bl {
-            self.reg_file = self.reg_file.set_lr();
+        // lr and sp are only touched in write_back, once the condition passed
+        if decoded_op == Operation::B || decoded_op == Operation::Bl {
             self.r_d_mem = self.r_d_mem.set(
                 PC as u64
             )
         }
-        if decoded_op == Operation::Push {
-            let next_sp = self.reg_file.get(SP as u64) - 8;
-            self.reg_file = self.reg_file.set(
-                SP as u64, next_sp
-            )
-        }
         self.write_regs = self.write_regs.set(
-            !(decoded_op == Operation::Cmp || decoded_op == Operation::Cmn || decoded_op == Operation::Teq || decoded_op == Operation::Tst)
+            !(decoded_op == Operation::Cmp || decoded_op == Operation::Cmn || decoded_op == Operation::Teq || decoded_op == Operation::Tst || decoded_op == Operation::Msr || decoded_op == Operation::Out || decoded_op == Operation::Cpsie || decoded_op == Operation::Cpsid || decoded_op == Operation::Eret)
         );
         self
     }
@@ -167,7 +461,9 @@ impl CoreSys {
             self.data_bus = self.data_bus.set(self.out_d_mem.get());
         } else {
             let (result, flags, interruption) = self.alu.cal(self.op.get(), self.out_b.get(), self.out_c.get());
-            self.int = self.int.set(interruption);
+            if interruption != 0 {
+                return self.raise(Fault::DivideByZero);
+            }
             self.data_bus = self.data_bus.set(result);
             if self.write_flags.get() {
                 self.reg_file = self.reg_file.set_cpsr(flags);
@@ -182,6 +478,36 @@ impl CoreSys {
             self.query = self.query.set(
                 self.data_bus.get()
             );
+            let query = self.query.get();
+            if let Some(answer) = self.devices.iter_mut().find_map(|attached| attached.device.query(query)) {
+                self.int_data = self.int_data.set(answer);
+            }
+        } else if op == Operation::Mrs {
+            match self.read_sys_reg(self.out_c.get()) {
+                Ok(val) => self.data_bus = self.data_bus.set(val),
+                Err(fault) => return self.raise(fault),
+            }
+        } else if op == Operation::Msr {
+            if let Err(fault) = self.write_sys_reg(self.out_c.get(), self.out_b.get()) {
+                return self.raise(fault);
+            }
+        } else if op == Operation::In {
+            let port = self.out_c.get();
+            match self.port_in(port) {
+                Ok(val) => self.data_bus = self.data_bus.set(val),
+                Err(fault) => return self.raise_at(fault, port),
+            }
+        } else if op == Operation::Out {
+            let port = self.out_c.get();
+            if let Err(fault) = self.port_out(port, self.out_b.get()) {
+                return self.raise_at(fault, port);
+            }
+        } else if op == Operation::Cpsie {
+            self.reg_file = self.reg_file.set_irq_masked(false);
+        } else if op == Operation::Cpsid {
+            self.reg_file = self.reg_file.set_irq_masked(true);
+        } else if op == Operation::Eret {
+            self.reg_file = self.reg_file.restore_state();
         }
         self
     }
@@ -190,33 +516,43 @@ impl CoreSys {
             self.mem_cal.calculate(self.out_m_b.get(), self.out_m_o.get(), self.out_m_s.get())
         );
         let op_code = Operation::new(self.op.get());
-        match op_code {
+        let addr = match op_code {
+            Operation::Push => self.reg_file.get(SP as u64).wrapping_sub(8),
+            Operation::Pop => self.reg_file.get(SP as u64),
+            _ => self.addr_bus.get(),
+        };
+        let result = match op_code {
             Operation::Ldr => {
-                self.data_bus = self.data_bus.set(
-                    self.memory.get_word(self.addr_bus.get())
-                );
+                self.read_word(addr, Access::Read).map(|val| {
+                    self.data_bus = self.data_bus.set(val);
+                })
             },
             Operation::Str => {
-                self.memory = self.memory.set_word(self.addr_bus.get(), self.data_bus.get());
+                self.write_word(addr, self.data_bus.get())
             },
             Operation::Push => {
-                self.memory = self.memory.set_word(self.reg_file.get(SP as u64), self.data_bus.get());
+                self.write_word(addr, self.data_bus.get())
             }
             Operation::Pop => {
-                self.data_bus = self.data_bus.set(
-                    self.memory.get_word(self.reg_file.get(SP as u64))
-                )
+                self.read_word(addr, Access::Read).map(|val| {
+                    self.data_bus = self.data_bus.set(val);
+                })
             }
             _ => {
-
+                Ok(())
             }
         };
-        self
+        match result {
+            Ok(()) => self,
+            Err(fault) => self.raise_at(fault, addr),
+        }
     }
-    pub fn interrupt(mut self, int: u64, data: u64) -> CoreSys {
-        self.int = self.int.set(int);
-        self.int_data = self.int_data.set(data);
-        self
+    // raise an irq line on the interrupt controller, it stays pending until taken
+    // lines below FIRST_IRQ belong to the exceptions and are refused
+    pub fn interrupt(&mut self, int: u64, data: u64) -> Result<(), Fault> {
+        self.int_ctrl.raise(int, data)?;
+        self.int = self.int.set(self.int_ctrl.peek().unwrap_or(0));
+        Ok(())
     }
     pub fn write_back(mut self) -> CoreSys {
         let op: Operation = decode_op(self.op.get());
@@ -225,6 +561,15 @@ impl CoreSys {
             self.reg_file = self.reg_file.set(
                 SP as u64, next_sp
             )
+        } else if op == Operation::Push {
+            let next_sp = self.reg_file.get(SP as u64) - 8;
+            self.reg_file = self.reg_file.set(
+                SP as u64, next_sp
+            );
+            // the pushed register was stored, not loaded
+            return self;
+        } else if op == Operation::Bl {
+            self.reg_file = self.reg_file.set_lr();
         }
         self.reg_file = self.reg_file.set(
             self.r_d_mem.get(), self.data_bus.get()
@@ -232,11 +577,7 @@ impl CoreSys {
         self
     }
     pub fn load_mem(mut self, val: Vec<u8>) -> CoreSys {
-        let mut loaded_mem = vec![0; MEM_SIZE];
-        for i in 0..val.len() {
-            loaded_mem[i] = val[i];
-        }
-        self.memory = Mem::new(loaded_mem);
+        self.memory.load(&val);
         self
     }
     pub fn set_pc_sp(mut self) -> CoreSys {
@@ -244,49 +585,171 @@ impl CoreSys {
             PC as u64, 0
         );
         self.reg_file = self.reg_file.set(
-            SP as u64, self.memory.get_size() as u64
+            SP as u64, self.memory.get_size()
         );
         self
     }
     pub fn halted(&self) -> bool {
         let op = Operation::new(self.op.get());
-        op == Operation::Hlt
+        // a hlt that is not taken falls through to the next instruction
+        op == Operation::Hlt && self.reg_file.get_cond(self.cond.get())
+    }
+
+    pub fn step(mut self) -> Result<CoreSys, StepError> {
+        if self.fault.is_some() {
+            return Err(StepError::new(self));
+        }
+        self = self.cycle();
+        self = self.tick_devices();
+        match self.fault {
+            Some(fault) => self.take_exception(fault),
+            None => Ok(self),
+        }
+    }
+    pub fn clear_fault(mut self) -> CoreSys {
+        self.fault = None;
+        self
+    }
+    // back to the state after loading, memory and devices keep their contents
+    pub fn reset(mut self) -> CoreSys {
+        self.reg_file = RegFile::new();
+        self.mmu = Mmu::new();
+        self.vbar = None;
+        self.fault = None;
+        self.nmi = None;
+        self.int_ctrl.acknowledge(!0);
+        self.op = self.op.set(0);
+        self.instr = self.instr.set(0);
+        self.resets += 1;
+        self.set_pc_sp()
     }
+    pub fn get_reset_count(&self) -> u64 {
+        self.resets
+    }
+}
 
-    pub fn step(mut self) -> CoreSys {
+// host resources for the native build
+#[cfg(not(target_arch = "wasm32"))]
+impl CoreSys {
+    // the machine is left as it was when the image cannot be read or attached
+    pub fn attach_disk_image(&mut self, base: u64, irq: u64, path: impl AsRef<Path>) -> io::Result<()> {
+        let image = fs::read(path)?;
+        self.attach_block_device(base, irq, image)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", err)))
+    }
+    pub fn save_disk_image(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.get_block_image())
+    }
+    // the samples rendered since they were last taken, as a wav file
+    pub fn save_audio_wav(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, super::audio::wav(&self.take_audio_samples()))
+    }
+    // an rng seeded from the os instead of a fixed seed
+    pub fn attach_entropy_rng(&mut self, base: u64) -> Result<(), AttachError> {
+        self.attach(base, Box::new(Rng::from_entropy()))
+    }
+}
+
+impl CoreSys {
+    // plug a device into the io bus, its registers are mapped at base
+    // base has to be word aligned so that the registers are too
+    pub fn attach(&mut self, base: u64, device: Box<dyn Device>) -> Result<(), AttachError> {
+        if !base.is_multiple_of(WORD_SIZE) {
+            return Err(AttachError::Unaligned);
+        }
+        let size = device.size();
+        if size > 0 {
+            self.memory.map(Region::new(base, size, RegionKind::Mmio, PERM_R | PERM_W));
+        }
+        self.devices.push(AttachedDevice { base: Some(base), port: None, device });
+        Ok(())
+    }
+    // a device that only answers in and out, its registers are not mapped in memory
+    pub fn attach_ports(mut self, port: u64, device: Box<dyn Device>) -> CoreSys {
+        self.devices.push(AttachedDevice { base: None, port: Some(port), device });
+        self
+    }
+    // the first attached device of type T
+    pub fn device<T: Device + 'static>(&mut self) -> Option<&mut T> {
+        self.devices
+            .iter_mut()
+            .find_map(|attached| attached.device.as_mut().as_any().downcast_mut::<T>())
+    }
+    pub fn framebuffer_ppm(&mut self) -> Option<Vec<u8>> {
+        self.device::<Framebuffer>().map(|fb| fb.ppm())
+    }
+    fn cycle(mut self) -> CoreSys {
         if self.halted() {
             return self;
         }
-        if self.int.get() != 0 {
-            // bl to the interrupt handler
-            let handler = self.int_table[self.int.get() as usize];
-            self.reg_file = self.reg_file.set_lr();
-            self.reg_file = self.reg_file.set(
-                PC as u64, handler
-            );
-            self.int = self.int.set(0);
-            return self;
+        if let Some(data) = self.nmi.take() {
+            return match self.vector(Exception::Nmi as u64) {
+                Some(handler) => {
+                    self.int_data = self.int_data.set(data);
+                    self.enter_handler(handler)
+                }
+                None => {
+                    self.pc_mem = self.pc_mem.set(self.reg_file.get_pc());
+                    self.instr = self.instr.set(0);
+                    self.raise(Fault::MissingVector)
+                }
+            };
+        }
+        if let (false, Some(int)) = (self.reg_file.irq_masked(), self.int_ctrl.peek()) {
+            let handler = match self.vector(int) {
+                Some(handler) => handler,
+                None => {
+                    self.pc_mem = self.pc_mem.set(self.reg_file.get_pc());
+                    self.instr = self.instr.set(0);
+                    return self.raise(Fault::MissingVector);
+                }
+            };
+            if let Some((_, data)) = self.int_ctrl.claim() {
+                self.int_data = self.int_data.set(data);
+            }
+            self.int = self.int.set(self.int_ctrl.peek().unwrap_or(0));
+            return self.enter_handler(handler);
         }
         self = self.fetch();
+        if self.fault.is_some() {
+            return self;
+        }
         self = self.decode();
-        let op = Operation::new(self.op.get());
-        if op == Operation::Nop {
+        if self.fault.is_some() {
             return self;
-        } else if op == Operation::Hlt {
+        }
+        let op = Operation::new(self.op.get());
+        let taken = self.reg_file.get_cond(self.cond.get());
+        if op == Operation::Nop || op == Operation::Hlt {
             return self;
         }
         self = self.read_reg();
-        if self.reg_file.get_cond(self.cond.get()) {
+        if taken {
+            // a privileged instruction that is not taken does not fault
+            if op.privileged() && self.reg_file.user_mode() {
+                return self.raise(Fault::PrivilegeViolation);
+            }
             if op == Operation::Int {
                 let out_b = self.out_b.get();
                 let out_c = self.out_c.get();
-                self = self.interrupt(out_b, out_c);
+                if let Err(fault) = self.interrupt(out_b, out_c) {
+                    return self.raise(fault);
+                }
                 return self;
             }
+            if op == Operation::Svc {
+                return self.supervisor_call();
+            }
             self = self.execute();
+            if self.fault.is_some() {
+                return self;
+            }
             let op_type = OperationType::new(self.op.get());
             if op_type == OperationType::Mem {
                 self = self.mem();
+                if self.fault.is_some() {
+                    return self;
+                }
             }
             if self.write_regs.get() {
                 self = self.write_back();
@@ -294,6 +757,174 @@ impl CoreSys {
         }
         self
     }
+    // an entry of 0 in the guest table, or one that cannot be read, is a missing vector
+    fn vector(&mut self, num: u64) -> Option<u64> {
+        match self.vbar {
+            Some(vbar) => {
+                let addr = vbar.checked_add(num.checked_mul(WORD_SIZE)?)?;
+                // the table is read with kernel rights even when user code was interrupted
+                let addr = self.mmu.translate(&self.memory, addr, Access::Read, false).ok()?;
+                self.read_phys(addr, Access::Read).ok().filter(|handler| *handler != 0)
+            }
+            None => self.int_table.get(num as usize).copied(),
+        }
+    }
+    // the return address and status go to elr and spsr, eret restores them
+    // irqs stay masked in the handler until it runs cpsie or returns
+    fn enter_handler(mut self, handler: u64) -> CoreSys {
+        self.reg_file = self.reg_file.save_state().set_irq_masked(true).set_user_mode(false);
+        self.reg_file = self.reg_file.set(
+            PC as u64, handler
+        );
+        self
+    }
+    // svc enters kernel mode with the immediate in int_data, elr is the next instruction
+    fn supervisor_call(mut self) -> CoreSys {
+        match self.vector(Exception::Svc as u64) {
+            Some(handler) => {
+                self.int_data = self.int_data.set(self.out_c.get());
+                self.enter_handler(handler)
+            }
+            None => self.raise(Fault::MissingVector),
+        }
+    }
+    // hand the fault to the guest if it installed a handler, otherwise stop
+    // elr holds the faulting pc, int_data the faulting address or instruction
+    fn take_exception(mut self, fault: Fault) -> Result<CoreSys, StepError> {
+        let handler = fault.exception().and_then(|exception| self.vector(exception as u64));
+        match handler {
+            Some(handler) => {
+                let data = match fault.exception() {
+                    Some(Exception::MemoryFault) | Some(Exception::MisalignedAccess) | Some(Exception::PageFault) => self.fault_addr,
+                    _ => self.fault_instr,
+                };
+                self.fault = None;
+                self.int_data = self.int_data.set(data);
+                Ok(self.enter_handler(handler))
+            }
+            None => Err(StepError::new(self)),
+        }
+    }
+    // virtual addresses go through the mmu before reaching memory, with the rights of the current mode
+    fn read_word(&mut self, addr: u64, access: Access) -> Result<u64, Fault> {
+        let addr = self.mmu.translate(&self.memory, addr, access, self.reg_file.user_mode())?;
+        self.read_phys(addr, access)
+    }
+    fn write_word(&mut self, addr: u64, val: u64) -> Result<(), Fault> {
+        let addr = self.mmu.translate(&self.memory, addr, Access::Write, self.reg_file.user_mode())?;
+        self.write_phys(addr, val)
+    }
+    // mmio regions are routed to the device mapped there
+    fn read_phys(&mut self, addr: u64, access: Access) -> Result<u64, Fault> {
+        if self.memory.kind(addr, access)? == RegionKind::Mmio {
+            if self.int_ctrl.contains(addr) {
+                return self.int_ctrl.read(addr);
+            }
+            let attached = self.device_at(addr)?;
+            let offset = addr - attached.base.unwrap_or(0);
+            return attached.device.read(offset);
+        }
+        self.memory.get_word(addr, access)
+    }
+    fn write_phys(&mut self, addr: u64, val: u64) -> Result<(), Fault> {
+        if self.memory.kind(addr, Access::Write)? == RegionKind::Mmio {
+            if self.int_ctrl.contains(addr) {
+                return self.int_ctrl.write(addr, val);
+            }
+            let attached = self.device_at(addr)?;
+            let offset = addr - attached.base.unwrap_or(0);
+            return attached.device.write(offset, val);
+        }
+        self.memory.set_word(addr, val)
+    }
+    fn port_in(&mut self, port: u64) -> Result<u64, Fault> {
+        let attached = self.device_at_port(port)?;
+        let first = attached.port.unwrap_or(0);
+        attached.device.port_in(port - first)
+    }
+    fn port_out(&mut self, port: u64, val: u64) -> Result<(), Fault> {
+        let attached = self.device_at_port(port)?;
+        let first = attached.port.unwrap_or(0);
+        attached.device.port_out(port - first, val)
+    }
+    fn device_at_port(&mut self, port: u64) -> Result<&mut AttachedDevice, Fault> {
+        self.devices
+            .iter_mut()
+            .find(|attached| attached.contains_port(port))
+            .ok_or(Fault::BusError)
+    }
+    fn device_at(&mut self, addr: u64) -> Result<&mut AttachedDevice, Fault> {
+        self.devices
+            .iter_mut()
+            .find(|attached| attached.contains(addr))
+            .ok_or(Fault::BusError)
+    }
+    fn tick_devices(mut self) -> CoreSys {
+        self.bus_owner = 0;
+        for i in 0..self.devices.len() {
+            self.devices[i].device.tick();
+            if let Some(transfer) = self.devices[i].device.bus_request() {
+                self.bus_owner = i as u64 + 1;
+                let result = self
+                    .read_phys(transfer.src, Access::Read)
+                    .and_then(|val| self.write_phys(transfer.dst, val));
+                self.devices[i].device.bus_complete(result);
+            }
+            if let Some((int, data)) = self.devices[i].device.take_interrupt() {
+                if let Err(fault) = self.interrupt(int, data) {
+                    self = self.raise(fault);
+                }
+            }
+            match self.devices[i].device.take_signal() {
+                Some(Signal::Nmi(data)) => self.nmi = Some(data),
+                Some(Signal::Reset) => self = self.reset(),
+                None => {}
+            }
+        }
+        self
+    }
+    fn read_sys_reg(&self, num: u64) -> Result<u64, Fault> {
+        let val = match SysReg::try_new(num)? {
+            SysReg::Ptbr => self.mmu.get_ptbr(),
+            SysReg::MmuCtl => self.mmu.get_ctl(),
+            SysReg::Elr => self.reg_file.get_elr(),
+            SysReg::Spsr => self.reg_file.get_spsr(),
+            SysReg::Vbar => self.vbar.unwrap_or(0),
+            SysReg::Usp => self.reg_file.get_banked_sp(),
+            SysReg::TlbFlush => 0,
+        };
+        Ok(val)
+    }
+    fn write_sys_reg(&mut self, num: u64, val: u64) -> Result<(), Fault> {
+        match SysReg::try_new(num)? {
+            SysReg::Ptbr => self.mmu.set_ptbr(val),
+            SysReg::MmuCtl => self.mmu.set_ctl(val),
+            SysReg::Elr => self.reg_file = mem::take(&mut self.reg_file).set_elr(val),
+            SysReg::Spsr => self.reg_file = mem::take(&mut self.reg_file).set_spsr(val),
+            SysReg::Vbar => self.vbar = Some(val),
+            SysReg::Usp => self.reg_file = mem::take(&mut self.reg_file).set_banked_sp(val),
+            SysReg::TlbFlush => self.mmu.flush(),
+        }
+        Ok(())
+    }
+    fn raise_at(mut self, fault: Fault, addr: u64) -> CoreSys {
+        if self.fault.is_none() {
+            self.fault_addr = addr;
+        }
+        self.raise(fault)
+    }
+    // record the fault and rewind pc to the faulting instruction
+    fn raise(mut self, fault: Fault) -> CoreSys {
+        if self.fault.is_none() {
+            self.fault = Some(fault);
+            self.fault_pc = self.pc_mem.get();
+            self.fault_instr = self.instr.get();
+            self.reg_file = self.reg_file.set(
+                PC as u64, self.fault_pc
+            );
+        }
+        self
+    }
 }
 
 #[wasm_bindgen]
@@ -302,7 +933,10 @@ impl CoreSys {
         self.reg_file.get(idx)
     }
     pub fn get_next_instr(&self) -> u64 {
-        self.memory.get_word(self.reg_file.get_pc())
+        self.mmu
+            .peek(&self.memory, self.reg_file.get_pc(), Access::Execute, self.reg_file.user_mode())
+            .and_then(|addr| self.memory.get_word(addr, Access::Execute))
+            .unwrap_or(0)
     }
     pub fn print(&self) {
         println!("op: {:b}", self.op.get());
@@ -328,6 +962,7 @@ impl CoreSys {
         println!("Register,");
         println!("{:?}", self.reg_file.dump_common());
         println!("Interruption: {}", self.int.get());
+        println!("Fault: {:?}", self.fault);
     }
     pub fn dump_int_table(&self) -> Vec<u64> {
         self.int_table.clone()
@@ -341,4 +976,43 @@ impl CoreSys {
     pub fn get_int(&self) -> u64 {
         self.int.get()
     }
+    pub fn get_irq_pending(&self) -> u64 {
+        self.int_ctrl.get_pending()
+    }
+    pub fn get_irq_enabled(&self) -> u64 {
+        self.int_ctrl.get_enabled()
+    }
+    pub fn get_user_mode(&self) -> bool {
+        self.reg_file.user_mode()
+    }
+    pub fn get_irq_masked(&self) -> bool {
+        self.reg_file.irq_masked()
+    }
+    pub fn get_vbar(&self) -> u64 {
+        self.vbar.unwrap_or(0)
+    }
+    pub fn get_elr(&self) -> u64 {
+        self.reg_file.get_elr()
+    }
+    pub fn get_spsr(&self) -> u64 {
+        self.reg_file.get_spsr()
+    }
+    pub fn get_fault(&self) -> Option<Fault> {
+        self.fault
+    }
+    pub fn get_fault_pc(&self) -> u64 {
+        self.fault_pc
+    }
+    pub fn get_fault_instr(&self) -> u64 {
+        self.fault_instr
+    }
+    pub fn get_fault_addr(&self) -> u64 {
+        self.fault_addr
+    }
+    pub fn get_tlb_hits(&self) -> u64 {
+        self.mmu.get_hits()
+    }
+    pub fn get_tlb_misses(&self) -> u64 {
+        self.mmu.get_misses()
+    }
 }
\ No newline at end of file
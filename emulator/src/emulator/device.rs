@@ -0,0 +1,111 @@
+use std::any::Any;
+
+use wasm_bindgen::prelude::*;
+
+use super::fault::Fault;
+use super::mem::WORD_SIZE;
+
+pub trait AsAny {
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// a one word copy between physical addresses, asked for by a bus master
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BusTransfer {
+    pub src: u64,
+    pub dst: u64,
+}
+
+// why a device could not be attached with the given configuration
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttachError {
+    // a dimension is zero
+    Empty = 1,
+    TooLarge = 2,
+    UnsupportedBpp = 3,
+    // the registers would not be word aligned
+    Unaligned = 4,
+    // the irq line is an exception vector or past the last line
+    ReservedIrq = 5,
+}
+
+// raised by a device past the interrupt controller
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Signal {
+    // taken even with interrupts masked, with the interrupt data
+    Nmi(u64),
+    Reset,
+}
+
+// a peripheral sitting on the io bus behind the io controller
+// registers are words addressed by their byte offset from the base address
+pub trait Device: AsAny {
+    // size of the mmio window in bytes, 0 if the device has no registers
+    fn size(&self) -> u64 {
+        0
+    }
+    fn read(&mut self, _offset: u64) -> Result<u64, Fault> {
+        Err(Fault::BusError)
+    }
+    fn write(&mut self, _offset: u64, _val: u64) -> Result<(), Fault> {
+        Err(Fault::BusError)
+    }
+    // io ports reached by in and out, counted from the first port the device is mapped at
+    // by default every register is also a port, in register order
+    fn ports(&self) -> u64 {
+        self.size() / WORD_SIZE
+    }
+    fn port_in(&mut self, port: u64) -> Result<u64, Fault> {
+        self.read(port * WORD_SIZE)
+    }
+    fn port_out(&mut self, port: u64, val: u64) -> Result<(), Fault> {
+        self.write(port * WORD_SIZE, val)
+    }
+    // called once per cycle
+    fn tick(&mut self) {}
+    // answer a qry from the guest, the answer is read back with mvi
+    fn query(&mut self, _val: u64) -> Option<u64> {
+        None
+    }
+    // a bus master gets at most one transfer per cycle, done after its tick
+    fn bus_request(&mut self) -> Option<BusTransfer> {
+        None
+    }
+    fn bus_complete(&mut self, _result: Result<(), Fault>) {}
+    // an interrupt to raise, as irq line from FIRST_IRQ up and interrupt data
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        None
+    }
+    fn take_signal(&mut self) -> Option<Signal> {
+        None
+    }
+}
+
+pub struct AttachedDevice {
+    // the mmio base address and the first io port, for the spaces the device is mapped in
+    pub base: Option<u64>,
+    pub port: Option<u64>,
+    pub device: Box<dyn Device>,
+}
+
+impl AttachedDevice {
+    pub fn contains(&self, addr: u64) -> bool {
+        match self.base {
+            Some(base) => addr >= base && addr - base < self.device.size(),
+            None => false,
+        }
+    }
+    pub fn contains_port(&self, port: u64) -> bool {
+        match self.port {
+            Some(first) => port >= first && port - first < self.device.ports(),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use super::device::{BusTransfer, Device};
+use super::fault::Fault;
+use super::mem::WORD_SIZE;
+
+// mmio registers, src and dst are physical addresses and len is in bytes
+// src, dst and len count along with the transfer
+pub const DMA_SRC: u64 = 0x00;
+pub const DMA_DST: u64 = 0x08;
+pub const DMA_LEN: u64 = 0x10;
+pub const DMA_CTRL: u64 = 0x18;
+pub const DMA_STATUS: u64 = 0x20;
+pub const DMA_SIZE: u64 = 0x28;
+
+// control bits, a fixed address is not advanced, for device data registers
+pub const DMA_START: u64 = 0b0001;
+pub const DMA_IRQ_ENABLE: u64 = 0b0010;
+pub const DMA_SRC_FIXED: u64 = 0b0100;
+pub const DMA_DST_FIXED: u64 = 0b1000;
+
+// status bits, write done or error to clear them
+pub const DMA_BUSY: u64 = 0b001;
+pub const DMA_DONE: u64 = 0b010;
+pub const DMA_ERROR: u64 = 0b100;
+
+// copies len bytes a word per cycle, the cpu keeps running meanwhile
+// a bus error stops the copy with src, dst and len left at the failed word
+pub struct Dma {
+    irq: u64,
+    src: u64,
+    dst: u64,
+    len: u64,
+    ctrl: u64,
+    status: u64,
+    finished: bool,
+}
+
+impl Dma {
+    pub fn new(irq: u64) -> Dma {
+        Dma {
+            irq,
+            src: 0,
+            dst: 0,
+            len: 0,
+            ctrl: 0,
+            status: 0,
+            finished: false,
+        }
+    }
+    // src, dst, len, ctrl, status
+    pub fn dump(&self) -> Vec<u64> {
+        vec![self.src, self.dst, self.len, self.ctrl, self.status]
+    }
+    fn finish(&mut self, status: u64) {
+        self.status = status;
+        self.ctrl &= !DMA_START;
+        self.finished = self.ctrl & DMA_IRQ_ENABLE != 0;
+    }
+}
+
+impl Device for Dma {
+    fn size(&self) -> u64 {
+        DMA_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            DMA_SRC => Ok(self.src),
+            DMA_DST => Ok(self.dst),
+            DMA_LEN => Ok(self.len),
+            DMA_CTRL => Ok(self.ctrl),
+            DMA_STATUS => Ok(self.status),
+            _ => Err(Fault::BusError),
+        }
+    }
+    // the registers are locked while a copy runs
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        if self.status & DMA_BUSY != 0 && offset != DMA_STATUS {
+            return Err(Fault::BusError);
+        }
+        match offset {
+            DMA_SRC => self.src = val,
+            DMA_DST => self.dst = val,
+            DMA_LEN => self.len = val,
+            DMA_CTRL => {
+                self.ctrl = val;
+                if val & DMA_START != 0 {
+                    if self.len.is_multiple_of(WORD_SIZE) {
+                        self.status = DMA_BUSY;
+                    } else {
+                        self.finish(DMA_ERROR);
+                    }
+                }
+            }
+            DMA_STATUS => self.status &= !(val & (DMA_DONE | DMA_ERROR)),
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    fn tick(&mut self) {
+        if self.status & DMA_BUSY != 0 && self.len == 0 {
+            self.finish(DMA_DONE);
+        }
+    }
+    fn bus_request(&mut self) -> Option<BusTransfer> {
+        if self.status & DMA_BUSY == 0 {
+            return None;
+        }
+        Some(BusTransfer {
+            src: self.src,
+            dst: self.dst,
+        })
+    }
+    fn bus_complete(&mut self, result: Result<(), Fault>) {
+        if result.is_err() {
+            return self.finish(DMA_ERROR);
+        }
+        if self.ctrl & DMA_SRC_FIXED == 0 {
+            self.src += WORD_SIZE;
+        }
+        if self.ctrl & DMA_DST_FIXED == 0 {
+            self.dst += WORD_SIZE;
+        }
+        self.len -= WORD_SIZE;
+        if self.len == 0 {
+            self.finish(DMA_DONE);
+        }
+    }
+    // the interrupt data is the status after the copy
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if self.finished {
+            self.finished = false;
+            Some((self.irq, self.status))
+        } else {
+            None
+        }
+    }
+}
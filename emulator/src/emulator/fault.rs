@@ -0,0 +1,95 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use super::CoreSys;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    UndefinedInstruction = 1,
+    BusError = 2,
+    UnalignedAccess = 3,
+    MissingVector = 4,
+    DivideByZero = 5,
+    AccessViolation = 6,
+    PageFault = 7,
+    PrivilegeViolation = 8,
+    // an irq line below FIRST_IRQ or past the last line
+    ReservedIrq = 9,
+}
+
+// exception numbers index the same vector table as irqs, below FIRST_IRQ so the two never meet
+// the guest handles a fault by installing a handler under its number
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exception {
+    DivideByZero = 1,
+    UndefinedInstruction = 2,
+    MemoryFault = 3,
+    MisalignedAccess = 4,
+    PrivilegeViolation = 5,
+    PageFault = 6,
+    Svc = 7,
+    Nmi = 8,
+}
+
+impl Fault {
+    pub fn exception(&self) -> Option<Exception> {
+        match self {
+            Fault::UndefinedInstruction => Some(Exception::UndefinedInstruction),
+            Fault::BusError => Some(Exception::MemoryFault),
+            Fault::UnalignedAccess => Some(Exception::MisalignedAccess),
+            Fault::MissingVector => None,
+            Fault::DivideByZero => Some(Exception::DivideByZero),
+            Fault::AccessViolation => Some(Exception::MemoryFault),
+            Fault::PageFault => Some(Exception::PageFault),
+            Fault::PrivilegeViolation => Some(Exception::PrivilegeViolation),
+            Fault::ReservedIrq => None,
+        }
+    }
+}
+
+// returned by step when the core stops on a fault
+// the core is kept inside so that the host can inspect it and carry on
+#[wasm_bindgen]
+pub struct StepError {
+    core: Box<CoreSys>,
+}
+
+impl StepError {
+    pub fn new(core: CoreSys) -> StepError {
+        StepError { core: Box::new(core) }
+    }
+}
+
+#[wasm_bindgen]
+impl StepError {
+    pub fn fault(&self) -> Fault {
+        self.core.get_fault().expect("step error without a fault")
+    }
+    pub fn pc(&self) -> u64 {
+        self.core.get_fault_pc()
+    }
+    pub fn instr(&self) -> u64 {
+        self.core.get_fault_instr()
+    }
+    pub fn addr(&self) -> u64 {
+        self.core.get_fault_addr()
+    }
+    pub fn into_core(self) -> CoreSys {
+        *self.core
+    }
+}
+
+impl fmt::Debug for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} at pc {:#x}, instr {:#018x}",
+            self.fault(),
+            self.pc(),
+            self.instr()
+        )
+    }
+}
@@ -0,0 +1,171 @@
+use super::device::{AttachError, Device};
+use super::fault::Fault;
+use super::mem::WORD_SIZE;
+
+// mmio registers, width, height, bpp and frame are read only
+pub const FB_CTRL: u64 = 0x00;
+pub const FB_WIDTH: u64 = 0x08;
+pub const FB_HEIGHT: u64 = 0x10;
+pub const FB_BPP: u64 = 0x18;
+// cycles between two vsyncs, 0 stops them
+pub const FB_VSYNC: u64 = 0x20;
+// number of vsyncs so far
+pub const FB_FRAME: u64 = 0x28;
+// one 0xrrggbb word per color index
+pub const FB_PALETTE: u64 = 0x100;
+pub const FB_PALETTE_SIZE: u64 = 256;
+pub const FB_PIXELS: u64 = FB_PALETTE + FB_PALETTE_SIZE * WORD_SIZE;
+
+// control bits
+pub const FB_VSYNC_IRQ_ENABLE: u64 = 0b1;
+
+pub const FB_DEFAULT_VSYNC: u64 = 1000;
+pub const FB_MAX_PIXELS: u64 = 4096 * 4096;
+
+// indexed color pixels packed into words, the first pixel in the highest bits
+// so that pixels appear in memory order
+pub struct Framebuffer {
+    width: u64,
+    height: u64,
+    bpp: u64,
+    irq: u64,
+    ctrl: u64,
+    vsync: u64,
+    cycles: u64,
+    frame: u64,
+    vsync_pending: bool,
+    palette: Vec<u64>,
+    pixels: Vec<u64>,
+}
+
+impl Framebuffer {
+    // bpp is 1, 2, 4 or 8, the palette starts as a gray ramp
+    pub fn new(width: u64, height: u64, bpp: u64, irq: u64) -> Result<Framebuffer, AttachError> {
+        if !matches!(bpp, 1 | 2 | 4 | 8) {
+            return Err(AttachError::UnsupportedBpp);
+        }
+        let pixels = width.checked_mul(height).ok_or(AttachError::TooLarge)?;
+        if pixels == 0 {
+            return Err(AttachError::Empty);
+        }
+        if pixels > FB_MAX_PIXELS {
+            return Err(AttachError::TooLarge);
+        }
+        let colors = 1 << bpp;
+        let palette = (0..FB_PALETTE_SIZE)
+            .map(|i| {
+                let gray = (i.min(colors - 1) * 255) / (colors - 1);
+                gray << 16 | gray << 8 | gray
+            })
+            .collect();
+        let words = (pixels * bpp).div_ceil(64);
+        Ok(Framebuffer {
+            width,
+            height,
+            bpp,
+            irq,
+            ctrl: 0,
+            vsync: FB_DEFAULT_VSYNC,
+            cycles: 0,
+            frame: 0,
+            vsync_pending: false,
+            palette,
+            pixels: vec![0; words as usize],
+        })
+    }
+    pub fn get_width(&self) -> u64 {
+        self.width
+    }
+    pub fn get_height(&self) -> u64 {
+        self.height
+    }
+    pub fn get_frame(&self) -> u64 {
+        self.frame
+    }
+    pub fn pixel(&self, x: u64, y: u64) -> u64 {
+        let bit = (y * self.width + x) * self.bpp;
+        let shift = 64 - self.bpp - bit % 64;
+        (self.pixels[(bit / 64) as usize] >> shift) & ((1 << self.bpp) - 1)
+    }
+    pub fn rgb(&self, x: u64, y: u64) -> [u8; 3] {
+        let color = self.palette[self.pixel(x, y) as usize];
+        [(color >> 16) as u8, (color >> 8) as u8, color as u8]
+    }
+    // row major, 4 bytes per pixel
+    pub fn rgba(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity((self.width * self.height * 4) as usize);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                ret.extend_from_slice(&self.rgb(x, y));
+                ret.push(0xff);
+            }
+        }
+        ret
+    }
+    // binary ppm, for golden image tests outside the browser
+    pub fn ppm(&self) -> Vec<u8> {
+        let mut ret = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                ret.extend_from_slice(&self.rgb(x, y));
+            }
+        }
+        ret
+    }
+}
+
+impl Device for Framebuffer {
+    fn size(&self) -> u64 {
+        FB_PIXELS + self.pixels.len() as u64 * WORD_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            FB_CTRL => Ok(self.ctrl),
+            FB_WIDTH => Ok(self.width),
+            FB_HEIGHT => Ok(self.height),
+            FB_BPP => Ok(self.bpp),
+            FB_VSYNC => Ok(self.vsync),
+            FB_FRAME => Ok(self.frame),
+            _ if (FB_PALETTE..FB_PIXELS).contains(&offset) => {
+                Ok(self.palette[((offset - FB_PALETTE) / WORD_SIZE) as usize])
+            }
+            _ if offset >= FB_PIXELS => Ok(self.pixels[((offset - FB_PIXELS) / WORD_SIZE) as usize]),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            FB_CTRL => self.ctrl = val,
+            FB_VSYNC => {
+                self.vsync = val;
+                self.cycles = 0;
+            }
+            _ if (FB_PALETTE..FB_PIXELS).contains(&offset) => {
+                self.palette[((offset - FB_PALETTE) / WORD_SIZE) as usize] = val & 0xff_ffff;
+            }
+            _ if offset >= FB_PIXELS => self.pixels[((offset - FB_PIXELS) / WORD_SIZE) as usize] = val,
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    fn tick(&mut self) {
+        if self.vsync == 0 {
+            return;
+        }
+        self.cycles += 1;
+        if self.cycles == self.vsync {
+            self.cycles = 0;
+            self.frame += 1;
+            self.vsync_pending = self.ctrl & FB_VSYNC_IRQ_ENABLE != 0;
+        }
+    }
+    // the interrupt data is the frame number
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if self.vsync_pending {
+            self.vsync_pending = false;
+            Some((self.irq, self.frame))
+        } else {
+            None
+        }
+    }
+}
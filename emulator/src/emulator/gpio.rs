@@ -0,0 +1,148 @@
+use super::device::Device;
+use super::fault::Fault;
+
+// mmio registers
+// out drives the leds, bit n lights led n
+pub const GPIO_OUT: u64 = 0x00;
+// seven segment digits, one byte each with digit 0 in the lowest byte
+pub const GPIO_SEG: u64 = 0x08;
+// switches and buttons, read only
+pub const GPIO_IN: u64 = 0x10;
+// input pins that interrupt on a rising or a falling edge
+pub const GPIO_RISE: u64 = 0x18;
+pub const GPIO_FALL: u64 = 0x20;
+// latched edges on the enabled pins, write 1 to clear
+pub const GPIO_EDGES: u64 = 0x28;
+pub const GPIO_SIZE: u64 = 0x30;
+
+pub const GPIO_SEG_DIGITS: u64 = 8;
+// one bit per pin in each register
+pub const GPIO_PINS: u64 = 64;
+
+// segment bits, a to g clockwise from the top then the middle, and the decimal point
+pub const SEG_A: u8 = 1 << 0;
+pub const SEG_B: u8 = 1 << 1;
+pub const SEG_C: u8 = 1 << 2;
+pub const SEG_D: u8 = 1 << 3;
+pub const SEG_E: u8 = 1 << 4;
+pub const SEG_F: u8 = 1 << 5;
+pub const SEG_G: u8 = 1 << 6;
+pub const SEG_DP: u8 = 1 << 7;
+
+// segments lit for the hex digits 0 to f
+pub const SEG_HEX: [u8; 16] = [
+    SEG_A | SEG_B | SEG_C | SEG_D | SEG_E | SEG_F,
+    SEG_B | SEG_C,
+    SEG_A | SEG_B | SEG_D | SEG_E | SEG_G,
+    SEG_A | SEG_B | SEG_C | SEG_D | SEG_G,
+    SEG_B | SEG_C | SEG_F | SEG_G,
+    SEG_A | SEG_C | SEG_D | SEG_F | SEG_G,
+    SEG_A | SEG_C | SEG_D | SEG_E | SEG_F | SEG_G,
+    SEG_A | SEG_B | SEG_C,
+    SEG_A | SEG_B | SEG_C | SEG_D | SEG_E | SEG_F | SEG_G,
+    SEG_A | SEG_B | SEG_C | SEG_D | SEG_F | SEG_G,
+    SEG_A | SEG_B | SEG_C | SEG_E | SEG_F | SEG_G,
+    SEG_C | SEG_D | SEG_E | SEG_F | SEG_G,
+    SEG_A | SEG_D | SEG_E | SEG_F,
+    SEG_B | SEG_C | SEG_D | SEG_E | SEG_G,
+    SEG_A | SEG_D | SEG_E | SEG_F | SEG_G,
+    SEG_A | SEG_E | SEG_F | SEG_G,
+];
+
+// the digit shown by a segment pattern, ignoring the decimal point
+pub fn seg_to_hex(seg: u8) -> Option<u8> {
+    SEG_HEX.iter().position(|hex| *hex == seg & !SEG_DP).map(|digit| digit as u8)
+}
+
+// the board, the host flips the inputs and reads back the outputs every step
+// an edge interrupts once, when it is latched
+pub struct Gpio {
+    irq: u64,
+    out: u64,
+    seg: u64,
+    input: u64,
+    rise: u64,
+    fall: u64,
+    edges: u64,
+    new_edges: bool,
+}
+
+impl Gpio {
+    pub fn new(irq: u64) -> Gpio {
+        Gpio {
+            irq,
+            out: 0,
+            seg: 0,
+            input: 0,
+            rise: 0,
+            fall: 0,
+            edges: 0,
+            new_edges: false,
+        }
+    }
+    pub fn set_inputs(&mut self, input: u64) {
+        let edges = (input & !self.input & self.rise) | (!input & self.input & self.fall);
+        self.input = input;
+        self.new_edges |= edges & !self.edges != 0;
+        self.edges |= edges;
+    }
+    // pins past the last one do not exist and are ignored
+    pub fn set_input(&mut self, pin: u64, level: bool) {
+        if pin >= GPIO_PINS {
+            return;
+        }
+        let input = if level {
+            self.input | 1 << pin
+        } else {
+            self.input & !(1 << pin)
+        };
+        self.set_inputs(input);
+    }
+    pub fn leds(&self) -> u64 {
+        self.out
+    }
+    pub fn digits(&self) -> Vec<u8> {
+        (0..GPIO_SEG_DIGITS).map(|digit| (self.seg >> (digit * 8)) as u8).collect()
+    }
+    // out, seg, in, edges
+    pub fn dump(&self) -> Vec<u64> {
+        vec![self.out, self.seg, self.input, self.edges]
+    }
+}
+
+impl Device for Gpio {
+    fn size(&self) -> u64 {
+        GPIO_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            GPIO_OUT => Ok(self.out),
+            GPIO_SEG => Ok(self.seg),
+            GPIO_IN => Ok(self.input),
+            GPIO_RISE => Ok(self.rise),
+            GPIO_FALL => Ok(self.fall),
+            GPIO_EDGES => Ok(self.edges),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            GPIO_OUT => self.out = val,
+            GPIO_SEG => self.seg = val,
+            GPIO_RISE => self.rise = val,
+            GPIO_FALL => self.fall = val,
+            GPIO_EDGES => self.edges &= !val,
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    // the interrupt data is the latched edges
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if self.new_edges {
+            self.new_edges = false;
+            Some((self.irq, self.edges))
+        } else {
+            None
+        }
+    }
+}
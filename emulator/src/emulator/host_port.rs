@@ -0,0 +1,46 @@
+use super::device::Device;
+use super::fault::Fault;
+
+// a range of io ports answered by the host, for hosts such as js that cannot hand over a device
+// in reads the last value the host set for the port, out is queued until the host takes it
+pub struct HostPorts {
+    first: u64,
+    values: Vec<u64>,
+    writes: Vec<(u64, u64)>,
+}
+
+impl HostPorts {
+    pub fn new(first: u64, count: u64) -> HostPorts {
+        HostPorts {
+            first,
+            values: vec![0; count as usize],
+            writes: Vec::new(),
+        }
+    }
+    // ports outside the range are ignored
+    pub fn set(&mut self, port: u64, val: u64) {
+        if let Some(slot) = port.checked_sub(self.first).and_then(|index| self.values.get_mut(index as usize)) {
+            *slot = val;
+        }
+    }
+    // port and value of every out since the last take, oldest first
+    pub fn take_writes(&mut self) -> Vec<(u64, u64)> {
+        std::mem::take(&mut self.writes)
+    }
+}
+
+impl Device for HostPorts {
+    fn ports(&self) -> u64 {
+        self.values.len() as u64
+    }
+    fn port_in(&mut self, port: u64) -> Result<u64, Fault> {
+        self.values.get(port as usize).copied().ok_or(Fault::BusError)
+    }
+    fn port_out(&mut self, port: u64, val: u64) -> Result<(), Fault> {
+        if port >= self.ports() {
+            return Err(Fault::BusError);
+        }
+        self.writes.push((self.first + port, val));
+        Ok(())
+    }
+}
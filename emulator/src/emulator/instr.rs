@@ -1,5 +1,8 @@
 use wasm_bindgen::prelude::*;
 
+use super::fault::Fault;
+use super::sys_reg::SysReg;
+
 #[wasm_bindgen]
 #[derive(Clone, Copy, Debug)]
 pub struct Instr {
@@ -53,6 +56,13 @@ impl Instr {
 pub enum Operation {
     Nop = 0b00_0000_0000_0000,
     Hlt = 0b00_0000_0000_0001,
+    Msr = 0b00_0000_0001_0000,
+    Mrs = 0b00_0000_0001_0001,
+    Cpsie = 0b00_0000_0010_0000,
+    Cpsid = 0b00_0000_0010_0001,
+    Eret = 0b00_0000_0010_0010,
+    In = 0b00_0000_0011_0000,
+    Out = 0b00_0000_0011_0001,
     Mov = 0b01_0000_0000_0000,
     Add = 0b01_0000_0000_0001,
     Sub = 0b01_0000_0000_0010,
@@ -81,6 +91,7 @@ pub enum Operation {
     Mvi = 0b01_0000_0011_0000,
     Qry = 0b01_0000_0011_0001,
     Int = 0b01_0000_0011_0010,
+    Svc = 0b01_0000_0011_0011,
 
     Ldr = 0b10_0000_0000_0000,
     Str = 0b10_0000_0000_0001,
@@ -95,9 +106,22 @@ pub enum Operation {
 impl Operation {
     #[wasm_bindgen(constructor)]
     pub fn new(op_code: u64) -> Operation {
-        match op_code {
+        Operation::try_new(op_code).expect("invalid instr")
+    }
+}
+
+impl Operation {
+    pub fn try_new(op_code: u64) -> Result<Operation, Fault> {
+        let op = match op_code {
             0b00_0000_0000_0000 => Operation::Nop,
             0b00_0000_0000_0001 => Operation::Hlt,
+            0b00_0000_0001_0000 => Operation::Msr,
+            0b00_0000_0001_0001 => Operation::Mrs,
+            0b00_0000_0010_0000 => Operation::Cpsie,
+            0b00_0000_0010_0001 => Operation::Cpsid,
+            0b00_0000_0010_0010 => Operation::Eret,
+            0b00_0000_0011_0000 => Operation::In,
+            0b00_0000_0011_0001 => Operation::Out,
             0b01_0000_0000_0000 => Operation::Mov,
             0b01_0000_0000_0001 => Operation::Add,
             0b01_0000_0000_0010 => Operation::Sub,
@@ -125,6 +149,7 @@ impl Operation {
             0b01_0000_0011_0000 => Operation::Mvi,
             0b01_0000_0011_0001 => Operation::Qry,
             0b01_0000_0011_0010 => Operation::Int,
+            0b01_0000_0011_0011 => Operation::Svc,
 
             0b10_0000_0000_0000 => Operation::Ldr,
             0b10_0000_0000_0001 => Operation::Str,
@@ -133,8 +158,16 @@ impl Operation {
 
             0b11_0000_0000_0000 => Operation::B,
             0b11_0000_0000_0001 => Operation::Bl,
-            _ => panic!("invalid instr"),
-        }
+            _ => return Err(Fault::UndefinedInstruction),
+        };
+        Ok(op)
+    }
+    // only kernel mode may run these
+    pub fn privileged(&self) -> bool {
+        matches!(
+            self,
+            Operation::Msr | Operation::Mrs | Operation::Cpsie | Operation::Cpsid | Operation::Eret | Operation::In | Operation::Out
+        )
     }
 }
 
@@ -210,6 +243,13 @@ fn reg_c_to_string(decoded: Instr) -> String {
     }
 }
 
+fn sys_reg_to_string(decoded: Instr) -> String {
+    match SysReg::try_new(decoded.reg_c) {
+        Ok(sys_reg) => String::from(sys_reg.name()),
+        Err(_) => format!("#{}", decoded.reg_c),
+    }
+}
+
 fn generate_memo_addr(decoded: Instr) -> String {
     // ra, rb, rc
     if decoded.c_is_imm && decoded.reg_c == 0 {
@@ -222,11 +262,21 @@ fn generate_memo_addr(decoded: Instr) -> String {
 #[wasm_bindgen]
 pub fn instr_to_string(instr: u64) -> String {
     let decoded = Instr::new(instr);
-    let op = decode_op(decoded.op_code);
+    let op = match Operation::try_new(decoded.op_code) {
+        Ok(op) if decoded.cond_code <= 0b1110 => op,
+        _ => return format!("undefined {:#018x}", instr),
+    };
     // convert to string
     match op {
-        Operation::Nop => String::from("nop"),
-        Operation::Hlt => String::from("hlt"),
+        Operation::Nop => format!("nop{}", generate_postfix(decoded)),
+        Operation::Hlt => format!("hlt{}", generate_postfix(decoded)),
+        Operation::Msr => format!("msr{} {}, r{}", generate_postfix(decoded), sys_reg_to_string(decoded), decoded.reg_b),
+        Operation::Mrs => format!("mrs{} r{}, {}", generate_postfix(decoded), decoded.reg_d_mem, sys_reg_to_string(decoded)),
+        Operation::Cpsie => format!("cpsie{}", generate_postfix(decoded)),
+        Operation::Cpsid => format!("cpsid{}", generate_postfix(decoded)),
+        Operation::Eret => format!("eret{}", generate_postfix(decoded)),
+        Operation::In => format!("in{} r{}, {}", generate_postfix(decoded), decoded.reg_d_mem, reg_c_to_string(decoded)),
+        Operation::Out => format!("out{} {}, r{}", generate_postfix(decoded), reg_c_to_string(decoded), decoded.reg_b),
         Operation::Mov => format!("mov{} r{}, {}", generate_postfix(decoded), decoded.reg_d_mem, reg_c_to_string(decoded)),
         Operation::Add => format!("add{} r{}, r{}, {}", generate_postfix(decoded), decoded.reg_d_mem, decoded.reg_b, reg_c_to_string(decoded)),
         Operation::Sub => format!("sub{} r{}, r{}, {}", generate_postfix(decoded), decoded.reg_d_mem, decoded.reg_b, reg_c_to_string(decoded)),
@@ -253,6 +303,7 @@ pub fn instr_to_string(instr: u64) -> String {
         Operation::Mvi => format!("mvi{} r{}", generate_postfix(decoded), decoded.reg_d_mem),
         Operation::Qry => format!("qry{} {}", generate_postfix(decoded), reg_c_to_string(decoded)),
         Operation::Int => format!("int{} r{}, {}", generate_postfix(decoded), decoded.reg_b, reg_c_to_string(decoded)),
+        Operation::Svc => format!("svc{} {}", generate_postfix(decoded), reg_c_to_string(decoded)),
 
         Operation::Ldr => format!("ldr{} r{}, {}", generate_postfix(decoded), decoded.reg_d_mem, generate_memo_addr(decoded)),
         Operation::Str => format!("str{} r{}, {}", generate_postfix(decoded), decoded.reg_d_mem, generate_memo_addr(decoded)),
@@ -0,0 +1,127 @@
+use super::fault::Fault;
+
+// one line per vector number
+pub const IRQ_LINES: u64 = 64;
+// vectors below this are exceptions, lines under it cannot be raised
+pub const FIRST_IRQ: u64 = 16;
+
+// whether a line can be raised at all
+pub fn is_irq(line: u64) -> bool {
+    (FIRST_IRQ..IRQ_LINES).contains(&line)
+}
+
+// mmio registers
+pub const INT_CTRL_PENDING: u64 = 0x00;
+pub const INT_CTRL_ENABLE: u64 = 0x08;
+pub const INT_CTRL_RAISE: u64 = 0x10;
+pub const INT_CTRL_CLAIM: u64 = 0x18;
+pub const INT_CTRL_PRIORITY: u64 = 0x100;
+pub const INT_CTRL_SIZE: u64 = INT_CTRL_PRIORITY + IRQ_LINES * 8;
+
+// a raised line stays pending until the cpu takes it or the guest acknowledges it
+// among the enabled pending lines the lowest priority value wins, then the lowest line
+pub struct IntCtrl {
+    pending: u64,
+    enabled: u64,
+    priority: Vec<u64>,
+    data: Vec<u64>,
+    base: Option<u64>,
+}
+
+impl Default for IntCtrl {
+    fn default() -> IntCtrl {
+        IntCtrl::new()
+    }
+}
+
+impl IntCtrl {
+    pub fn new() -> IntCtrl {
+        IntCtrl {
+            pending: 0,
+            enabled: !0,
+            priority: vec![0; IRQ_LINES as usize],
+            data: vec![0; IRQ_LINES as usize],
+            base: None,
+        }
+    }
+    pub fn raise(&mut self, line: u64, data: u64) -> Result<(), Fault> {
+        if !is_irq(line) {
+            return Err(Fault::ReservedIrq);
+        }
+        self.pending |= 1 << line;
+        self.data[line as usize] = data;
+        Ok(())
+    }
+    pub fn acknowledge(&mut self, mask: u64) {
+        self.pending &= !mask;
+    }
+    // the line the cpu would take next
+    pub fn peek(&self) -> Option<u64> {
+        let ready = self.pending & self.enabled;
+        (1..IRQ_LINES)
+            .filter(|line| ready & (1 << line) != 0)
+            .min_by_key(|line| self.priority[*line as usize])
+    }
+    // take the next line and its data, acknowledging it
+    pub fn claim(&mut self) -> Option<(u64, u64)> {
+        let line = self.peek()?;
+        self.acknowledge(1 << line);
+        Some((line, self.data[line as usize]))
+    }
+    pub fn get_pending(&self) -> u64 {
+        self.pending
+    }
+    pub fn get_enabled(&self) -> u64 {
+        self.enabled
+    }
+    pub fn set_enabled(&mut self, line: u64, enabled: bool) {
+        if line >= IRQ_LINES {
+            return;
+        }
+        if enabled {
+            self.enabled |= 1 << line;
+        } else {
+            self.enabled &= !(1 << line);
+        }
+    }
+    pub fn set_priority(&mut self, line: u64, priority: u64) {
+        if line < IRQ_LINES {
+            self.priority[line as usize] = priority;
+        }
+    }
+
+    pub fn map(&mut self, base: u64) {
+        self.base = Some(base);
+    }
+    pub fn contains(&self, addr: u64) -> bool {
+        match self.base {
+            Some(base) => addr >= base && addr - base < INT_CTRL_SIZE,
+            None => false,
+        }
+    }
+    pub fn read(&mut self, addr: u64) -> Result<u64, Fault> {
+        let offset = addr - self.base.ok_or(Fault::BusError)?;
+        match offset {
+            INT_CTRL_PENDING => Ok(self.pending),
+            INT_CTRL_ENABLE => Ok(self.enabled),
+            INT_CTRL_CLAIM => Ok(self.claim().map(|(line, _)| line).unwrap_or(0)),
+            _ if (INT_CTRL_PRIORITY..INT_CTRL_SIZE).contains(&offset) => {
+                Ok(self.priority[((offset - INT_CTRL_PRIORITY) / 8) as usize])
+            }
+            _ => Err(Fault::BusError),
+        }
+    }
+    pub fn write(&mut self, addr: u64, val: u64) -> Result<(), Fault> {
+        let offset = addr - self.base.ok_or(Fault::BusError)?;
+        match offset {
+            INT_CTRL_PENDING => self.acknowledge(val),
+            INT_CTRL_ENABLE => self.enabled = val,
+            INT_CTRL_RAISE => self.raise(val, 0).map_err(|_| Fault::BusError)?,
+            _ if (INT_CTRL_PRIORITY..INT_CTRL_SIZE).contains(&offset) => {
+                self.priority[((offset - INT_CTRL_PRIORITY) / 8) as usize] = val;
+            }
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+}
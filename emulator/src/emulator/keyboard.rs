@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+
+use super::device::Device;
+use super::fault::Fault;
+
+// mmio registers
+// reading data takes the oldest event, 0 when the queue is empty
+pub const KBD_DATA: u64 = 0x00;
+pub const KBD_STATUS: u64 = 0x08;
+pub const KBD_CTRL: u64 = 0x10;
+pub const KBD_COUNT: u64 = 0x18;
+pub const KBD_SIZE: u64 = 0x20;
+
+// status bits, write overflow to clear it
+pub const KBD_READY: u64 = 0b01;
+pub const KBD_OVERFLOW: u64 = 0b10;
+
+// control bits
+pub const KBD_IRQ_ENABLE: u64 = 0b1;
+
+// an event is pressed << 16 | modifiers << 8 | key code
+pub const KEY_PRESSED: u64 = 1 << 16;
+pub const MOD_SHIFT: u8 = 0b0001;
+pub const MOD_CTRL: u8 = 0b0010;
+pub const MOD_ALT: u8 = 0b0100;
+pub const MOD_META: u8 = 0b1000;
+
+// events past this are dropped and set the overflow bit
+pub const KBD_QUEUE_SIZE: usize = 16;
+
+pub fn key_event(code: u8, pressed: bool, modifiers: u8) -> u64 {
+    let pressed = if pressed { KEY_PRESSED } else { 0 };
+    pressed | (modifiers as u64) << 8 | code as u64
+}
+
+// the interrupt is raised when the queue goes from empty to non empty
+pub struct Keyboard {
+    irq: u64,
+    ctrl: u64,
+    overflow: bool,
+    events: VecDeque<u64>,
+    arrived: bool,
+}
+
+impl Keyboard {
+    pub fn new(irq: u64) -> Keyboard {
+        Keyboard {
+            irq,
+            ctrl: 0,
+            overflow: false,
+            events: VecDeque::new(),
+            arrived: false,
+        }
+    }
+    pub fn push(&mut self, event: u64) {
+        if self.events.len() >= KBD_QUEUE_SIZE {
+            self.overflow = true;
+            return;
+        }
+        self.arrived |= self.events.is_empty();
+        self.events.push_back(event);
+    }
+    pub fn pending(&self) -> usize {
+        self.events.len()
+    }
+}
+
+impl Device for Keyboard {
+    fn size(&self) -> u64 {
+        KBD_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            KBD_DATA => Ok(self.events.pop_front().unwrap_or(0)),
+            KBD_STATUS => {
+                let ready = if self.events.is_empty() { 0 } else { KBD_READY };
+                let overflow = if self.overflow { KBD_OVERFLOW } else { 0 };
+                Ok(ready | overflow)
+            }
+            KBD_CTRL => Ok(self.ctrl),
+            KBD_COUNT => Ok(self.events.len() as u64),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            KBD_STATUS => self.overflow &= val & KBD_OVERFLOW == 0,
+            KBD_CTRL => self.ctrl = val,
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    // the interrupt data is the oldest event, which stays queued
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if !self.arrived || self.ctrl & KBD_IRQ_ENABLE == 0 {
+            return None;
+        }
+        self.arrived = false;
+        self.events.front().map(|event| (self.irq, *event))
+    }
+}
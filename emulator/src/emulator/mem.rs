@@ -1,35 +1,178 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use super::fault::Fault;
+use super::mem_map::{Access, MemMap, Region, RegionKind};
+
+pub const WORD_SIZE: u64 = 8;
+pub const PAGE_SIZE: u64 = 4 * 1024;
+// flat memory is allocated up front, larger spaces have to be sparse
+pub const MAX_FLAT_SIZE: u64 = 256 * 1024 * 1024;
+
+// why a memory size was refused
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemError {
+    Empty = 1,
+    // not a whole number of words
+    Unaligned = 2,
+    TooLarge = 3,
+}
+
+enum Backing {
+    Flat(Vec<u8>),
+    // page number to page, a page only exists once it has been written
+    Sparse(HashMap<u64, Vec<u8>>),
+}
+
 pub struct Mem {
-    mem: Vec<u8>,
+    backing: Backing,
+    size: u64,
+    map: MemMap,
 }
 
 impl Mem {
     pub fn new(val: Vec<u8>) -> Mem {
         Mem {
-            mem: val,
+            size: val.len() as u64,
+            map: MemMap::ram(val.len() as u64),
+            backing: Backing::Flat(val),
+        }
+    }
+    pub fn flat(size: u64) -> Result<Mem, MemError> {
+        Mem::check_size(size)?;
+        if size > MAX_FLAT_SIZE {
+            return Err(MemError::TooLarge);
+        }
+        Ok(Mem::new(vec![0; size as usize]))
+    }
+    pub fn sparse(size: u64) -> Result<Mem, MemError> {
+        Mem::check_size(size)?;
+        Ok(Mem {
+            size,
+            map: MemMap::ram(size),
+            backing: Backing::Sparse(HashMap::new()),
+        })
+    }
+    fn check_size(size: u64) -> Result<(), MemError> {
+        if size == 0 {
+            Err(MemError::Empty)
+        } else if !size.is_multiple_of(WORD_SIZE) {
+            Err(MemError::Unaligned)
+        } else {
+            Ok(())
+        }
+    }
+    pub fn kind(&self, addr: u64, access: Access) -> Result<RegionKind, Fault> {
+        if !addr.is_multiple_of(WORD_SIZE) {
+            return Err(Fault::UnalignedAccess);
+        }
+        Ok(self.map.check(addr, access)?.kind)
+    }
+    pub fn check(&self, addr: u64, access: Access) -> Result<u64, Fault> {
+        if self.kind(addr, access)? == RegionKind::Mmio {
+            return Err(Fault::BusError);
+        }
+        match addr.checked_add(WORD_SIZE) {
+            Some(end) if end <= self.size => Ok(addr),
+            _ => Err(Fault::BusError),
         }
     }
-    pub fn get_word(&self, addr: u64) -> u64 {
+    pub fn get_word(&self, addr: u64, access: Access) -> Result<u64, Fault> {
+        let addr = self.check(addr, access)?;
         let mut val = 0;
         for i in 0..8 {
-            val |= (self.mem[addr as usize + i] as u64) << (8 * (8 - i - 1));
+            val |= (self.get_byte(addr + i) as u64) << (8 * (8 - i - 1));
         }
-        val
+        Ok(val)
     }
-    pub fn set_word(mut self, addr: u64, val: u64) -> Mem {
+    pub fn set_word(&mut self, addr: u64, val: u64) -> Result<(), Fault> {
+        let addr = self.check(addr, Access::Write)?;
         for i in 0..8 {
-            self.mem[addr as usize + i] = ((val >> (8 * (8 - i - 1))) & 0xff) as u8;
+            self.set_byte(addr + i, ((val >> (8 * (8 - i - 1))) & 0xff) as u8);
         }
-        Mem {
-            mem: self.mem,
+        Ok(())
+    }
+    // clear the memory and copy val to address 0, bytes past the end are dropped
+    pub fn load(&mut self, val: &[u8]) {
+        match &mut self.backing {
+            Backing::Flat(mem) => mem.fill(0),
+            Backing::Sparse(pages) => pages.clear(),
+        }
+        let len = (val.len() as u64).min(self.size);
+        for i in 0..len {
+            self.set_byte(i, val[i as usize]);
+        }
+    }
+    pub fn map(&mut self, region: Region) {
+        self.map.map(region);
+    }
+    pub fn region(&self, addr: u64) -> Option<&Region> {
+        self.map.find(addr)
+    }
+    fn get_byte(&self, addr: u64) -> u8 {
+        match &self.backing {
+            Backing::Flat(mem) => mem[addr as usize],
+            Backing::Sparse(pages) => pages
+                .get(&(addr / PAGE_SIZE))
+                .map(|page| page[(addr % PAGE_SIZE) as usize])
+                .unwrap_or(0),
+        }
+    }
+    fn set_byte(&mut self, addr: u64, val: u8) {
+        match &mut self.backing {
+            Backing::Flat(mem) => mem[addr as usize] = val,
+            Backing::Sparse(pages) => {
+                let page = pages
+                    .entry(addr / PAGE_SIZE)
+                    .or_insert_with(|| vec![0; PAGE_SIZE as usize]);
+                page[(addr % PAGE_SIZE) as usize] = val;
+            }
         }
     }
 }
 
 impl Mem {
+    // sparse memory can be far too large to copy out whole, it is read by page or by range
     pub fn dump(&self) -> Vec<u8> {
-        self.mem.clone()
+        match &self.backing {
+            Backing::Flat(mem) => mem.clone(),
+            Backing::Sparse(_) => Vec::new(),
+        }
+    }
+    // the allocated pages in address order, as page number and contents
+    pub fn pages(&self) -> Vec<(u64, &[u8])> {
+        match &self.backing {
+            Backing::Flat(mem) => mem
+                .chunks(PAGE_SIZE as usize)
+                .enumerate()
+                .map(|(page, contents)| (page as u64, contents))
+                .collect(),
+            Backing::Sparse(pages) => {
+                let mut ret: Vec<(u64, &[u8])> = pages
+                    .iter()
+                    .map(|(page, contents)| (*page, contents.as_slice()))
+                    .collect();
+                ret.sort_by_key(|(page, _)| *page);
+                ret
+            }
+        }
+    }
+    pub fn dump_range(&self, addr: u64, len: u64) -> Vec<u8> {
+        let end = addr.saturating_add(len).min(self.size);
+        (addr.min(end)..end).map(|i| self.get_byte(i)).collect()
     }
-    pub fn get_size(&self) -> usize {
-        self.mem.len()
+    pub fn dump_regions(&self) -> Vec<u64> {
+        self.map.dump()
     }
-}
\ No newline at end of file
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+    pub fn allocated_pages(&self) -> u64 {
+        match &self.backing {
+            Backing::Flat(mem) => (mem.len() as u64).div_ceil(PAGE_SIZE),
+            Backing::Sparse(pages) => pages.len() as u64,
+        }
+    }
+}
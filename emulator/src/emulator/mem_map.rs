@@ -0,0 +1,91 @@
+use wasm_bindgen::prelude::*;
+
+use super::fault::Fault;
+
+pub const PERM_R: u8 = 0b100;
+pub const PERM_W: u8 = 0b010;
+pub const PERM_X: u8 = 0b001;
+pub const PERM_RWX: u8 = PERM_R | PERM_W | PERM_X;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionKind {
+    Ram = 0,
+    Rom = 1,
+    Mmio = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    pub fn perm(&self) -> u8 {
+        match self {
+            Access::Read => PERM_R,
+            Access::Write => PERM_W,
+            Access::Execute => PERM_X,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub base: u64,
+    pub size: u64,
+    pub kind: RegionKind,
+    pub perms: u8,
+}
+
+impl Region {
+    pub fn new(base: u64, size: u64, kind: RegionKind, perms: u8) -> Region {
+        // rom can never be written by the guest
+        let perms = match kind {
+            RegionKind::Rom => perms & !PERM_W,
+            _ => perms,
+        };
+        Region { base, size, kind, perms }
+    }
+    pub fn contains(&self, addr: u64) -> bool {
+        addr >= self.base && addr - self.base < self.size
+    }
+}
+
+// the regions mapped later take precedence where they overlap
+pub struct MemMap {
+    regions: Vec<Region>,
+}
+
+impl MemMap {
+    // the whole memory as read, write and execute ram
+    pub fn ram(size: u64) -> MemMap {
+        MemMap {
+            regions: vec![Region::new(0, size, RegionKind::Ram, PERM_RWX)],
+        }
+    }
+    pub fn map(&mut self, region: Region) {
+        self.regions.push(region);
+    }
+    pub fn find(&self, addr: u64) -> Option<&Region> {
+        self.regions.iter().rev().find(|region| region.contains(addr))
+    }
+    pub fn check(&self, addr: u64, access: Access) -> Result<&Region, Fault> {
+        let region = self.find(addr).ok_or(Fault::BusError)?;
+        if region.perms & access.perm() == 0 {
+            return Err(Fault::AccessViolation);
+        }
+        Ok(region)
+    }
+    // base, size, kind, perms for each region, in mapping order
+    pub fn dump(&self) -> Vec<u64> {
+        self.regions
+            .iter()
+            .flat_map(|region| {
+                vec![region.base, region.size, region.kind as u64, region.perms as u64]
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,132 @@
+use super::fault::Fault;
+use super::mem::{Mem, PAGE_SIZE};
+use super::mem_map::Access;
+
+// three levels of 512 entry tables, each table fills one page
+// a virtual address is 9 | 9 | 9 | 12 bits, the rest must be zero
+pub const PT_LEVELS: u64 = 3;
+pub const PT_INDEX_BITS: u64 = 9;
+pub const PAGE_BITS: u64 = 12;
+pub const VA_BITS: u64 = PAGE_BITS + PT_LEVELS * PT_INDEX_BITS;
+
+pub const PTE_VALID: u64 = 1 << 0;
+pub const PTE_R: u64 = 1 << 1;
+pub const PTE_W: u64 = 1 << 2;
+pub const PTE_X: u64 = 1 << 3;
+// user mode can only reach pages with this bit, kernel mode reaches every page
+pub const PTE_U: u64 = 1 << 4;
+pub const PTE_ADDR_MASK: u64 = !(PAGE_SIZE - 1);
+
+pub const MMUCTL_ENABLE: u64 = 1;
+
+pub const TLB_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug)]
+struct TlbEntry {
+    vpn: u64,
+    pte: u64,
+}
+
+#[derive(Default)]
+pub struct Mmu {
+    ptbr: u64,
+    ctl: u64,
+    // oldest entry first, replaced in fifo order
+    tlb: Vec<TlbEntry>,
+    hits: u64,
+    misses: u64,
+}
+
+impl Mmu {
+    pub fn new() -> Mmu {
+        Mmu::default()
+    }
+    pub fn enabled(&self) -> bool {
+        self.ctl & MMUCTL_ENABLE != 0
+    }
+    pub fn get_ptbr(&self) -> u64 {
+        self.ptbr
+    }
+    pub fn set_ptbr(&mut self, ptbr: u64) {
+        self.ptbr = ptbr;
+        self.flush();
+    }
+    pub fn get_ctl(&self) -> u64 {
+        self.ctl
+    }
+    pub fn set_ctl(&mut self, ctl: u64) {
+        self.ctl = ctl;
+        self.flush();
+    }
+    pub fn flush(&mut self) {
+        self.tlb.clear();
+    }
+    pub fn get_hits(&self) -> u64 {
+        self.hits
+    }
+    pub fn get_misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn translate(&mut self, mem: &Mem, va: u64, access: Access, user: bool) -> Result<u64, Fault> {
+        if !self.enabled() {
+            return Ok(va);
+        }
+        let vpn = va >> PAGE_BITS;
+        let pte = match self.tlb.iter().find(|entry| entry.vpn == vpn) {
+            Some(entry) => {
+                self.hits += 1;
+                entry.pte
+            }
+            None => {
+                self.misses += 1;
+                let pte = self.walk(mem, va)?;
+                if self.tlb.len() == TLB_SIZE {
+                    self.tlb.remove(0);
+                }
+                self.tlb.push(TlbEntry { vpn, pte });
+                pte
+            }
+        };
+        Mmu::resolve(pte, va, access, user)
+    }
+    // translate without touching the tlb, for the host peeking at memory
+    pub fn peek(&self, mem: &Mem, va: u64, access: Access, user: bool) -> Result<u64, Fault> {
+        if !self.enabled() {
+            return Ok(va);
+        }
+        Mmu::resolve(self.walk(mem, va)?, va, access, user)
+    }
+
+    fn walk(&self, mem: &Mem, va: u64) -> Result<u64, Fault> {
+        if va >> VA_BITS != 0 {
+            return Err(Fault::PageFault);
+        }
+        let mut table = self.ptbr;
+        for level in (0..PT_LEVELS).rev() {
+            let index = (va >> (PAGE_BITS + level * PT_INDEX_BITS)) & ((1 << PT_INDEX_BITS) - 1);
+            // a table at the very top of the address space must not wrap around
+            let entry = table.checked_add(index * 8).ok_or(Fault::PageFault)?;
+            let pte = mem.get_word(entry, Access::Read)?;
+            if pte & PTE_VALID == 0 {
+                return Err(Fault::PageFault);
+            }
+            if level == 0 {
+                return Ok(pte);
+            }
+            table = pte & PTE_ADDR_MASK;
+        }
+        unreachable!()
+    }
+    fn resolve(pte: u64, va: u64, access: Access, user: bool) -> Result<u64, Fault> {
+        let perm = match access {
+            Access::Read => PTE_R,
+            Access::Write => PTE_W,
+            Access::Execute => PTE_X,
+        };
+        if pte & perm == 0 || (user && pte & PTE_U == 0) {
+            return Err(Fault::PageFault);
+        }
+        Ok((pte & PTE_ADDR_MASK) | (va & (PAGE_SIZE - 1)))
+    }
+}
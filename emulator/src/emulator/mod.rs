@@ -1,12 +1,32 @@
+pub mod audio;
+pub mod block;
 pub mod core_sys;
 pub mod cpsr;
 pub mod decoder;
+pub mod device;
+pub mod dma;
+pub mod fault;
+pub mod framebuffer;
+pub mod gpio;
+pub mod host_port;
 pub mod instr;
+pub mod int_ctrl;
+pub mod keyboard;
 pub mod mem_addr_calculator;
 pub mod mem;
+pub mod mem_map;
+pub mod mmu;
+pub mod nic;
 pub mod reg_file;
 pub mod reg;
+pub mod rng;
+pub mod rtc;
+pub mod sys_reg;
+pub mod text_display;
+pub mod timer;
+pub mod uart;
 pub mod utils;
+pub mod watchdog;
 pub mod wire;
 pub mod alu;
 
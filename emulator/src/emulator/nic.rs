@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use super::device::Device;
+use super::fault::Fault;
+use super::mem::WORD_SIZE;
+use super::CoreSys;
+
+// a frame is dst mac, src mac, then the payload, one word each
+pub const NIC_MTU: u64 = 64;
+pub const NIC_BROADCAST: u64 = !0;
+// received frames past this are dropped
+pub const NIC_QUEUE_SIZE: usize = 16;
+
+// mmio registers
+pub const NIC_CTRL: u64 = 0x00;
+pub const NIC_STATUS: u64 = 0x08;
+pub const NIC_MAC: u64 = 0x10;
+// words to send from the tx buffer
+pub const NIC_TX_LEN: u64 = 0x18;
+// words in the received frame shown in the rx buffer, 0 when there is none
+pub const NIC_RX_LEN: u64 = 0x20;
+pub const NIC_CMD: u64 = 0x28;
+pub const NIC_TX_BUF: u64 = 0x100;
+pub const NIC_RX_BUF: u64 = NIC_TX_BUF + NIC_MTU * WORD_SIZE;
+pub const NIC_SIZE: u64 = NIC_RX_BUF + NIC_MTU * WORD_SIZE;
+
+// control bits
+pub const NIC_RX_IRQ_ENABLE: u64 = 0b1;
+
+// status bits, write tx error to clear it
+pub const NIC_RX_READY: u64 = 0b01;
+pub const NIC_TX_ERROR: u64 = 0b10;
+
+// commands, send and next can be given together
+pub const NIC_CMD_SEND: u64 = 0b01;
+// drop the frame in the rx buffer and show the next one
+pub const NIC_CMD_NEXT: u64 = 0b10;
+
+// the network card, frames leave through the outbox and arrive in the inbox
+// the switch moves them between machines
+pub struct Nic {
+    mac: u64,
+    irq: u64,
+    ctrl: u64,
+    tx_error: bool,
+    tx_len: u64,
+    tx_buf: Vec<u64>,
+    inbox: VecDeque<Vec<u64>>,
+    outbox: Vec<Vec<u64>>,
+    arrived: bool,
+}
+
+impl Nic {
+    pub fn new(mac: u64, irq: u64) -> Nic {
+        Nic {
+            mac,
+            irq,
+            ctrl: 0,
+            tx_error: false,
+            tx_len: 0,
+            tx_buf: vec![0; NIC_MTU as usize],
+            inbox: VecDeque::new(),
+            outbox: Vec::new(),
+            arrived: false,
+        }
+    }
+    pub fn mac(&self) -> u64 {
+        self.mac
+    }
+    pub fn take_sent(&mut self) -> Vec<Vec<u64>> {
+        std::mem::take(&mut self.outbox)
+    }
+    pub fn receive(&mut self, frame: Vec<u64>) {
+        if self.inbox.len() < NIC_QUEUE_SIZE {
+            self.arrived = true;
+            self.inbox.push_back(frame);
+        }
+    }
+    fn send(&mut self) {
+        if self.tx_len < 2 || self.tx_len > NIC_MTU {
+            self.tx_error = true;
+            return;
+        }
+        let mut frame = self.tx_buf[..self.tx_len as usize].to_vec();
+        frame[1] = self.mac;
+        self.outbox.push(frame);
+    }
+}
+
+impl Device for Nic {
+    fn size(&self) -> u64 {
+        NIC_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            NIC_CTRL => Ok(self.ctrl),
+            NIC_STATUS => {
+                let rx_ready = if self.inbox.is_empty() { 0 } else { NIC_RX_READY };
+                let tx_error = if self.tx_error { NIC_TX_ERROR } else { 0 };
+                Ok(rx_ready | tx_error)
+            }
+            NIC_MAC => Ok(self.mac),
+            NIC_TX_LEN => Ok(self.tx_len),
+            NIC_RX_LEN => Ok(self.inbox.front().map(|frame| frame.len() as u64).unwrap_or(0)),
+            _ if (NIC_TX_BUF..NIC_RX_BUF).contains(&offset) => {
+                Ok(self.tx_buf[((offset - NIC_TX_BUF) / WORD_SIZE) as usize])
+            }
+            _ if (NIC_RX_BUF..NIC_SIZE).contains(&offset) => {
+                let word = ((offset - NIC_RX_BUF) / WORD_SIZE) as usize;
+                Ok(self.inbox.front().and_then(|frame| frame.get(word)).copied().unwrap_or(0))
+            }
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            NIC_CTRL => self.ctrl = val,
+            NIC_STATUS => self.tx_error &= val & NIC_TX_ERROR == 0,
+            NIC_TX_LEN => self.tx_len = val,
+            NIC_CMD => {
+                if val & NIC_CMD_SEND != 0 {
+                    self.send();
+                }
+                if val & NIC_CMD_NEXT != 0 {
+                    self.inbox.pop_front();
+                }
+            }
+            _ if (NIC_TX_BUF..NIC_RX_BUF).contains(&offset) => {
+                self.tx_buf[((offset - NIC_TX_BUF) / WORD_SIZE) as usize] = val;
+            }
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    // the interrupt data is the number of frames waiting
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if !self.arrived || self.ctrl & NIC_RX_IRQ_ENABLE == 0 {
+            return None;
+        }
+        self.arrived = false;
+        Some((self.irq, self.inbox.len() as u64))
+    }
+}
+
+// the ports of the machines that faulted in a step
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct SwitchError {
+    ports: Vec<usize>,
+}
+
+#[wasm_bindgen]
+impl SwitchError {
+    pub fn ports(&self) -> Vec<usize> {
+        self.ports.clone()
+    }
+}
+
+// machines plugged into the ports of a switch step together
+// frames sent in a step are delivered after every machine has stepped, in port order
+// so the same programs always see the same traffic
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Switch {
+    machines: Vec<CoreSys>,
+}
+
+#[wasm_bindgen]
+impl Switch {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Switch {
+        Switch { machines: Vec::new() }
+    }
+    // the port the machine is plugged into
+    pub fn connect(&mut self, sys: CoreSys) -> usize {
+        self.machines.push(sys);
+        self.machines.len() - 1
+    }
+    pub fn into_machines(self) -> Vec<CoreSys> {
+        self.machines
+    }
+    pub fn halted(&self) -> bool {
+        self.machines.iter().all(|sys| sys.halted())
+    }
+    // the machine on a port, seen from js while it stays plugged in
+    // a port with nothing plugged in reads as halted and empty
+    pub fn port_halted(&self, port: usize) -> bool {
+        self.machines.get(port).map(|sys| sys.halted()).unwrap_or(true)
+    }
+    pub fn port_reg(&self, port: usize, idx: u64) -> u64 {
+        self.machines.get(port).map(|sys| sys.get_reg(idx)).unwrap_or(0)
+    }
+    pub fn port_fault(&self, port: usize) -> Option<Fault> {
+        self.machines.get(port).and_then(|sys| sys.get_fault())
+    }
+    pub fn port_console_write_input(&mut self, port: usize, input: &[u8]) {
+        if let Some(sys) = self.machines.get_mut(port) {
+            sys.console_write_input(input);
+        }
+    }
+    pub fn port_console_take_output(&mut self, port: usize) -> Vec<u8> {
+        self.machines.get_mut(port).map(|sys| sys.console_take_output()).unwrap_or_default()
+    }
+    // every machine steps even when another one faults
+    // a machine that faulted stays plugged in with its fault
+    pub fn step(&mut self) -> Result<(), SwitchError> {
+        let mut faulted = Vec::new();
+        self.machines = std::mem::take(&mut self.machines)
+            .into_iter()
+            .enumerate()
+            .map(|(port, sys)| match sys.step() {
+                Ok(sys) => sys,
+                Err(err) => {
+                    faulted.push(port);
+                    err.into_core()
+                }
+            })
+            .collect();
+        let mut frames = Vec::new();
+        for sys in self.machines.iter_mut() {
+            if let Some(nic) = sys.device::<Nic>() {
+                frames.extend(nic.take_sent());
+            }
+        }
+        for frame in frames {
+            for sys in self.machines.iter_mut() {
+                if let Some(nic) = sys.device::<Nic>() {
+                    let to_nic = frame[0] == nic.mac() || (frame[0] == NIC_BROADCAST && frame[1] != nic.mac());
+                    if to_nic {
+                        nic.receive(frame.clone());
+                    }
+                }
+            }
+        }
+        if faulted.is_empty() {
+            Ok(())
+        } else {
+            Err(SwitchError { ports: faulted })
+        }
+    }
+}
+
+impl Switch {
+    pub fn machine(&mut self, port: usize) -> &mut CoreSys {
+        &mut self.machines[port]
+    }
+}
@@ -1,4 +1,4 @@
-use super::{cpsr::Cpsr, reg::Reg};
+use super::{cpsr::Cpsr, fault::Fault, reg::Reg};
 
 #[derive(Debug)]
 pub enum ConditionCode {
@@ -21,7 +21,10 @@ pub enum ConditionCode {
 
 impl ConditionCode {
     pub fn from_u8(nzcv: u8) -> ConditionCode {
-        match nzcv {
+        ConditionCode::try_from_u8(nzcv).expect("Invalid condition code.")
+    }
+    pub fn try_from_u8(nzcv: u8) -> Result<ConditionCode, Fault> {
+        let cond = match nzcv {
             0b0000 => ConditionCode::EQ,
             0b0001 => ConditionCode::NE,
             0b0010 => ConditionCode::HS,
@@ -37,8 +40,9 @@ impl ConditionCode {
             0b1100 => ConditionCode::GT,
             0b1101 => ConditionCode::LE,
             0b1110 => ConditionCode::AL,
-            _ => panic!("Invalid condition code."),
-        }
+            _ => return Err(Fault::UndefinedInstruction),
+        };
+        Ok(cond)
     }
 }
 
@@ -47,10 +51,28 @@ pub const PC: usize = 15;
 pub const LR: usize = 14;
 pub const SP: usize = 13;
 
+// program status word, nzcv in the low bits and the mode and irq mask above them
+pub const PSR_U: u64 = 1 << 4;
+pub const PSR_I: u64 = 1 << 7;
+
 #[derive(Debug)]
 pub struct RegFile {
     regs: Vec<Reg>,
     cpsr: Cpsr,
+    // user mode cannot run privileged instructions, each mode has its own sp
+    user_mode: bool,
+    banked_sp: u64,
+    // interrupts are not taken while masked
+    irq_masked: bool,
+    // return address and status saved on exception entry
+    elr: u64,
+    spsr: u64,
+}
+
+impl Default for RegFile {
+    fn default() -> RegFile {
+        RegFile::new()
+    }
 }
 
 impl RegFile {
@@ -63,6 +85,11 @@ impl RegFile {
                 c: false,
                 v: false,
             },
+            user_mode: false,
+            banked_sp: 0,
+            irq_masked: false,
+            elr: 0,
+            spsr: 0,
         }
     }
 
@@ -88,6 +115,7 @@ impl RegFile {
                 c: self.cpsr.c,
                 v: self.cpsr.v,
             },
+            ..self
         }
     }
     pub fn get_cond(&self, cond_code: u64) -> bool {
@@ -113,8 +141,72 @@ impl RegFile {
     pub fn set_cpsr(self, nzcv: u8) -> RegFile {
         RegFile {
             regs: self.regs,
-            cpsr: Cpsr::from_u8(nzcv)
+            cpsr: Cpsr::from_u8(nzcv),
+            ..self
+        }
+    }
+    pub fn irq_masked(&self) -> bool {
+        self.irq_masked
+    }
+    pub fn set_irq_masked(mut self, masked: bool) -> RegFile {
+        self.irq_masked = masked;
+        self
+    }
+    pub fn user_mode(&self) -> bool {
+        self.user_mode
+    }
+    // switching mode swaps in the sp of the other mode
+    pub fn set_user_mode(mut self, user_mode: bool) -> RegFile {
+        if self.user_mode != user_mode {
+            let sp = self.regs[SP].get();
+            self.regs[SP] = Reg::new().set(self.banked_sp);
+            self.banked_sp = sp;
+            self.user_mode = user_mode;
         }
+        self
+    }
+    // the sp of the mode that is not running
+    pub fn get_banked_sp(&self) -> u64 {
+        self.banked_sp
+    }
+    pub fn set_banked_sp(mut self, sp: u64) -> RegFile {
+        self.banked_sp = sp;
+        self
+    }
+    pub fn get_psr(&self) -> u64 {
+        let user = if self.user_mode { PSR_U } else { 0 };
+        let masked = if self.irq_masked { PSR_I } else { 0 };
+        self.cpsr.to_u8() as u64 | user | masked
+    }
+    pub fn set_psr(mut self, psr: u64) -> RegFile {
+        self.cpsr = Cpsr::from_u8((psr & 0b1111) as u8);
+        self.irq_masked = psr & PSR_I != 0;
+        self.set_user_mode(psr & PSR_U != 0)
+    }
+    pub fn get_elr(&self) -> u64 {
+        self.elr
+    }
+    pub fn set_elr(mut self, elr: u64) -> RegFile {
+        self.elr = elr;
+        self
+    }
+    pub fn get_spsr(&self) -> u64 {
+        self.spsr
+    }
+    pub fn set_spsr(mut self, spsr: u64) -> RegFile {
+        self.spsr = spsr;
+        self
+    }
+    // save pc and status to elr and spsr on exception entry
+    pub fn save_state(mut self) -> RegFile {
+        self.elr = self.get_pc();
+        self.spsr = self.get_psr();
+        self
+    }
+    // eret, return to elr with the saved status
+    pub fn restore_state(self) -> RegFile {
+        let (elr, spsr) = (self.elr, self.spsr);
+        self.set(PC as u64, elr).set_psr(spsr)
     }
     pub fn get_pc(&self) -> u64 {
         self.regs[PC].get()
@@ -129,6 +221,7 @@ impl RegFile {
                 c: self.cpsr.c,
                 v: self.cpsr.v,
             },
+            ..self
         }
     }
     pub fn push_stack(mut self) -> RegFile {
@@ -141,6 +234,7 @@ impl RegFile {
                 c: self.cpsr.c,
                 v: self.cpsr.v,
             },
+            ..self
         }
     }
     pub fn pop_stack(mut self) -> RegFile {
@@ -153,6 +247,7 @@ impl RegFile {
                 c: self.cpsr.c,
                 v: self.cpsr.v,
             },
+            ..self
         }
     }
 
@@ -0,0 +1,74 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+use super::device::Device;
+use super::fault::Fault;
+
+// mmio registers
+// reading data gives the next word, writing seed restarts the sequence
+pub const RNG_DATA: u64 = 0x00;
+pub const RNG_SEED: u64 = 0x08;
+pub const RNG_SIZE: u64 = 0x10;
+
+// xorshift gets stuck on 0, so a 0 seed is replaced by this
+const RNG_ZERO_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// xorshift64*, the same seed always gives the same words
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng { seed: 0, state: 0 };
+        rng.set_seed(seed);
+        rng
+    }
+    // seeded from the os, for runs that do not need to be reproducible
+    // in the browser the host passes crypto.getRandomValues to set_seed instead
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_entropy() -> Rng {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|time| time.as_nanos())
+                .unwrap_or(0),
+        );
+        Rng::new(hasher.finish())
+    }
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.state = if seed == 0 { RNG_ZERO_SEED } else { seed };
+    }
+    pub fn next_word(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+impl Device for Rng {
+    fn size(&self) -> u64 {
+        RNG_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            RNG_DATA => Ok(self.next_word()),
+            RNG_SEED => Ok(self.seed),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            RNG_SEED => self.set_seed(val),
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+}
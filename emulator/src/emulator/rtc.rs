@@ -0,0 +1,109 @@
+use super::device::Device;
+use super::fault::Fault;
+
+// mmio registers, cycles is read only
+pub const RTC_TIME: u64 = 0x00;
+pub const RTC_CYCLES: u64 = 0x08;
+pub const RTC_ALARM: u64 = 0x10;
+pub const RTC_CTRL: u64 = 0x18;
+// bit 0 is set when the alarm goes off, write 1 to clear it
+pub const RTC_STATUS: u64 = 0x20;
+pub const RTC_SIZE: u64 = 0x28;
+
+// control bits
+pub const RTC_ALARM_ENABLE: u64 = 0b1;
+
+// qry ports answered with the time and the cycle count
+pub const RTC_QUERY_TIME: u64 = 0x100;
+pub const RTC_QUERY_CYCLES: u64 = 0x101;
+
+// emulated cycles in one second of wall clock time
+pub const RTC_CYCLES_PER_SECOND: u64 = 1_000_000;
+
+// the wall clock starts from the seconds the host gives it and advances with the cycles
+// so that runs with the same seed are deterministic
+// the alarm compares against the cycle count
+pub struct Rtc {
+    irq: u64,
+    seconds: u64,
+    since: u64,
+    cycles: u64,
+    alarm: u64,
+    ctrl: u64,
+    status: u64,
+    rang: bool,
+}
+
+impl Rtc {
+    pub fn new(irq: u64, seconds: u64) -> Rtc {
+        Rtc {
+            irq,
+            seconds,
+            since: 0,
+            cycles: 0,
+            alarm: 0,
+            ctrl: 0,
+            status: 0,
+            rang: false,
+        }
+    }
+    pub fn time(&self) -> u64 {
+        self.seconds.wrapping_add((self.cycles - self.since) / RTC_CYCLES_PER_SECOND)
+    }
+    pub fn set_time(&mut self, seconds: u64) {
+        self.seconds = seconds;
+        self.since = self.cycles;
+    }
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
+
+impl Device for Rtc {
+    fn size(&self) -> u64 {
+        RTC_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            RTC_TIME => Ok(self.time()),
+            RTC_CYCLES => Ok(self.cycles),
+            RTC_ALARM => Ok(self.alarm),
+            RTC_CTRL => Ok(self.ctrl),
+            RTC_STATUS => Ok(self.status),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            RTC_TIME => self.set_time(val),
+            RTC_ALARM => self.alarm = val,
+            RTC_CTRL => self.ctrl = val,
+            RTC_STATUS => self.status &= !val,
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    fn tick(&mut self) {
+        self.cycles += 1;
+        if self.ctrl & RTC_ALARM_ENABLE != 0 && self.cycles == self.alarm {
+            self.status |= 1;
+            self.rang = true;
+        }
+    }
+    fn query(&mut self, val: u64) -> Option<u64> {
+        match val {
+            RTC_QUERY_TIME => Some(self.time()),
+            RTC_QUERY_CYCLES => Some(self.cycles),
+            _ => None,
+        }
+    }
+    // the interrupt data is the cycle count
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if self.rang {
+            self.rang = false;
+            Some((self.irq, self.cycles))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use wasm_bindgen::prelude::*;
+
+use super::fault::Fault;
+
+// system registers, read with mrs and written with msr
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SysReg {
+    Ptbr = 0,
+    MmuCtl = 1,
+    Elr = 2,
+    Spsr = 3,
+    Vbar = 4,
+    Usp = 5,
+    // writing anything empties the tlb, reads as zero
+    TlbFlush = 6,
+}
+
+impl SysReg {
+    pub fn try_new(num: u64) -> Result<SysReg, Fault> {
+        match num {
+            0 => Ok(SysReg::Ptbr),
+            1 => Ok(SysReg::MmuCtl),
+            2 => Ok(SysReg::Elr),
+            3 => Ok(SysReg::Spsr),
+            4 => Ok(SysReg::Vbar),
+            5 => Ok(SysReg::Usp),
+            6 => Ok(SysReg::TlbFlush),
+            _ => Err(Fault::UndefinedInstruction),
+        }
+    }
+    pub fn from_name(name: &str) -> Option<SysReg> {
+        match name {
+            "ptbr" => Some(SysReg::Ptbr),
+            "mmuctl" => Some(SysReg::MmuCtl),
+            "elr" => Some(SysReg::Elr),
+            "spsr" => Some(SysReg::Spsr),
+            "vbar" => Some(SysReg::Vbar),
+            "usp" => Some(SysReg::Usp),
+            "tlbflush" => Some(SysReg::TlbFlush),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            SysReg::Ptbr => "ptbr",
+            SysReg::MmuCtl => "mmuctl",
+            SysReg::Elr => "elr",
+            SysReg::Spsr => "spsr",
+            SysReg::Vbar => "vbar",
+            SysReg::Usp => "usp",
+            SysReg::TlbFlush => "tlbflush",
+        }
+    }
+}
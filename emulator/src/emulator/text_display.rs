@@ -0,0 +1,92 @@
+use super::device::{AttachError, Device};
+use super::fault::Fault;
+use super::mem::WORD_SIZE;
+
+// mmio registers, cols and rows are read only
+pub const TEXT_COLS: u64 = 0x00;
+pub const TEXT_ROWS: u64 = 0x08;
+pub const TEXT_CURSOR_X: u64 = 0x10;
+pub const TEXT_CURSOR_Y: u64 = 0x18;
+// one word per cell, row major, attribute << 8 | char
+pub const TEXT_CELLS: u64 = 0x100;
+pub const TEXT_MAX_CELLS: u64 = 1024 * 1024;
+
+// a character cell display like the 80x25 text mode, the host reads it back as lines of text
+pub struct TextDisplay {
+    cols: u64,
+    rows: u64,
+    cursor_x: u64,
+    cursor_y: u64,
+    cells: Vec<u16>,
+}
+
+impl TextDisplay {
+    pub fn new(cols: u64, rows: u64) -> Result<TextDisplay, AttachError> {
+        let cells = cols.checked_mul(rows).ok_or(AttachError::TooLarge)?;
+        if cells == 0 {
+            return Err(AttachError::Empty);
+        }
+        if cells > TEXT_MAX_CELLS {
+            return Err(AttachError::TooLarge);
+        }
+        Ok(TextDisplay {
+            cols,
+            rows,
+            cursor_x: 0,
+            cursor_y: 0,
+            cells: vec![0; cells as usize],
+        })
+    }
+    pub fn cell(&self, x: u64, y: u64) -> (char, u8) {
+        let cell = self.cells[(y * self.cols + x) as usize];
+        let ch = match cell as u8 {
+            0 => ' ',
+            ch if ch.is_ascii_graphic() || ch == b' ' => ch as char,
+            _ => '?',
+        };
+        (ch, (cell >> 8) as u8)
+    }
+    // empty cells are spaces, trailing spaces are dropped
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.rows)
+            .map(|y| {
+                let line: String = (0..self.cols).map(|x| self.cell(x, y).0).collect();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+    // one attribute byte per cell, row major
+    pub fn attrs(&self) -> Vec<u8> {
+        self.cells.iter().map(|cell| (cell >> 8) as u8).collect()
+    }
+    pub fn cursor(&self) -> (u64, u64) {
+        (self.cursor_x, self.cursor_y)
+    }
+}
+
+impl Device for TextDisplay {
+    fn size(&self) -> u64 {
+        TEXT_CELLS + self.cells.len() as u64 * WORD_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            TEXT_COLS => Ok(self.cols),
+            TEXT_ROWS => Ok(self.rows),
+            TEXT_CURSOR_X => Ok(self.cursor_x),
+            TEXT_CURSOR_Y => Ok(self.cursor_y),
+            _ if offset >= TEXT_CELLS => Ok(self.cells[((offset - TEXT_CELLS) / WORD_SIZE) as usize] as u64),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            TEXT_CURSOR_X => self.cursor_x = val.min(self.cols.saturating_sub(1)),
+            TEXT_CURSOR_Y => self.cursor_y = val.min(self.rows.saturating_sub(1)),
+            _ if offset >= TEXT_CELLS => {
+                self.cells[((offset - TEXT_CELLS) / WORD_SIZE) as usize] = val as u16;
+            }
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+}
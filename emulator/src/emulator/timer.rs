@@ -0,0 +1,106 @@
+use super::device::Device;
+use super::fault::Fault;
+
+// mmio registers
+pub const TIMER_CTRL: u64 = 0x00;
+pub const TIMER_RELOAD: u64 = 0x08;
+pub const TIMER_COMPARE: u64 = 0x10;
+pub const TIMER_COUNT: u64 = 0x18;
+// bit 0 is set when the count reaches compare, write 1 to clear it
+pub const TIMER_STATUS: u64 = 0x20;
+pub const TIMER_SIZE: u64 = 0x28;
+
+// control bits
+pub const TIMER_ENABLE: u64 = 0b001;
+pub const TIMER_PERIODIC: u64 = 0b010;
+pub const TIMER_IRQ_ENABLE: u64 = 0b100;
+
+// counts up once per cycle while enabled and fires when the count reaches compare
+// a periodic timer starts again from reload, a one shot timer disables itself
+pub struct Timer {
+    irq: u64,
+    ctrl: u64,
+    reload: u64,
+    compare: u64,
+    count: u64,
+    status: u64,
+    fired: bool,
+}
+
+impl Timer {
+    pub fn new(irq: u64) -> Timer {
+        Timer {
+            irq,
+            ctrl: 0,
+            reload: 0,
+            compare: 0,
+            count: 0,
+            status: 0,
+            fired: false,
+        }
+    }
+    pub fn get_count(&self) -> u64 {
+        self.count
+    }
+    pub fn get_status(&self) -> u64 {
+        self.status
+    }
+}
+
+impl Device for Timer {
+    fn size(&self) -> u64 {
+        TIMER_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            TIMER_CTRL => Ok(self.ctrl),
+            TIMER_RELOAD => Ok(self.reload),
+            TIMER_COMPARE => Ok(self.compare),
+            TIMER_COUNT => Ok(self.count),
+            TIMER_STATUS => Ok(self.status),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            TIMER_CTRL => {
+                // enabling starts counting from reload
+                if self.ctrl & TIMER_ENABLE == 0 && val & TIMER_ENABLE != 0 {
+                    self.count = self.reload;
+                }
+                self.ctrl = val;
+            }
+            TIMER_RELOAD => self.reload = val,
+            TIMER_COMPARE => self.compare = val,
+            TIMER_COUNT => self.count = val,
+            TIMER_STATUS => self.status &= !val,
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    fn tick(&mut self) {
+        if self.ctrl & TIMER_ENABLE == 0 {
+            return;
+        }
+        self.count = self.count.wrapping_add(1);
+        if self.count != self.compare {
+            return;
+        }
+        self.status |= 1;
+        self.fired = self.ctrl & TIMER_IRQ_ENABLE != 0;
+        if self.ctrl & TIMER_PERIODIC != 0 {
+            self.count = self.reload;
+        } else {
+            self.ctrl &= !TIMER_ENABLE;
+        }
+    }
+    // the interrupt data is the compare value that was reached
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if self.fired {
+            self.fired = false;
+            Some((self.irq, self.compare))
+        } else {
+            None
+        }
+    }
+}
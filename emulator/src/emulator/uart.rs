@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+
+use super::device::Device;
+use super::fault::Fault;
+
+// mmio registers
+// reading data takes the next rx byte, writing it sends a tx byte
+pub const UART_DATA: u64 = 0x00;
+pub const UART_STATUS: u64 = 0x08;
+pub const UART_CTRL: u64 = 0x10;
+pub const UART_SIZE: u64 = 0x18;
+
+// status bits
+pub const UART_RX_READY: u64 = 0b01;
+pub const UART_TX_READY: u64 = 0b10;
+
+// control bits
+pub const UART_RX_IRQ_ENABLE: u64 = 0b01;
+
+// a serial console, the host fills rx and drains tx
+// the rx interrupt is raised when input arrives while it is enabled
+pub struct Uart {
+    irq: u64,
+    ctrl: u64,
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+    rx_arrived: bool,
+}
+
+impl Uart {
+    pub fn new(irq: u64) -> Uart {
+        Uart {
+            irq,
+            ctrl: 0,
+            rx: VecDeque::new(),
+            tx: Vec::new(),
+            rx_arrived: false,
+        }
+    }
+    pub fn write_input(&mut self, input: &[u8]) {
+        self.rx.extend(input);
+        self.rx_arrived |= !input.is_empty();
+    }
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.tx)
+    }
+    pub fn pending_input(&self) -> usize {
+        self.rx.len()
+    }
+}
+
+impl Device for Uart {
+    fn size(&self) -> u64 {
+        UART_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            // reading an empty rx gives 0
+            UART_DATA => Ok(self.rx.pop_front().unwrap_or(0) as u64),
+            UART_STATUS => {
+                let rx_ready = if self.rx.is_empty() { 0 } else { UART_RX_READY };
+                Ok(rx_ready | UART_TX_READY)
+            }
+            UART_CTRL => Ok(self.ctrl),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            UART_DATA => self.tx.push(val as u8),
+            UART_STATUS => {}
+            UART_CTRL => {
+                // enabling with input already waiting raises the interrupt too
+                if self.ctrl & UART_RX_IRQ_ENABLE == 0 && val & UART_RX_IRQ_ENABLE != 0 {
+                    self.rx_arrived |= !self.rx.is_empty();
+                }
+                self.ctrl = val;
+            }
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    // the interrupt data is the number of bytes waiting
+    fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+        if !self.rx_arrived || self.ctrl & UART_RX_IRQ_ENABLE == 0 {
+            return None;
+        }
+        self.rx_arrived = false;
+        Some((self.irq, self.rx.len() as u64))
+    }
+}
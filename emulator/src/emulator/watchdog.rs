@@ -0,0 +1,130 @@
+use super::device::{Device, Signal};
+use super::fault::Fault;
+
+// mmio registers, count is read only
+pub const WDT_CTRL: u64 = 0x00;
+// cycles between kicks, writing it reloads the count
+pub const WDT_TIMEOUT: u64 = 0x08;
+// writing the key reloads the count, other values are ignored
+pub const WDT_KICK: u64 = 0x10;
+pub const WDT_COUNT: u64 = 0x18;
+// write 1 to clear a bit
+pub const WDT_STATUS: u64 = 0x20;
+pub const WDT_SIZE: u64 = 0x28;
+
+pub const WDT_KICK_KEY: u64 = 0x5afe;
+
+// control bits
+pub const WDT_ENABLE: u64 = 0b01;
+// warn with a non maskable interrupt first and only reset if it expires again
+pub const WDT_NMI: u64 = 0b10;
+
+// status bits
+// the warning was given, cleared by a kick
+pub const WDT_STATUS_NMI: u64 = 0b01;
+// the last reset was done by the watchdog
+pub const WDT_STATUS_RESET: u64 = 0b10;
+
+// counts down every cycle while enabled and resets the machine when it runs out
+// the reset turns it off, so the rebooted kernel decides whether to use it again
+pub struct Watchdog {
+    ctrl: u64,
+    timeout: u64,
+    count: u64,
+    status: u64,
+    expirations: u64,
+    signal: Option<Signal>,
+}
+
+impl Watchdog {
+    pub fn new() -> Watchdog {
+        Watchdog {
+            ctrl: 0,
+            timeout: 0,
+            count: 0,
+            status: 0,
+            expirations: 0,
+            signal: None,
+        }
+    }
+    // times it ran out since it was attached
+    pub fn expirations(&self) -> u64 {
+        self.expirations
+    }
+    // ctrl, timeout, count, status, expirations
+    pub fn dump(&self) -> Vec<u64> {
+        vec![self.ctrl, self.timeout, self.count, self.status, self.expirations]
+    }
+    fn kick(&mut self) {
+        self.count = self.timeout;
+        self.status &= !WDT_STATUS_NMI;
+    }
+    fn expire(&mut self) {
+        self.expirations += 1;
+        if self.ctrl & WDT_NMI != 0 && self.status & WDT_STATUS_NMI == 0 {
+            self.status |= WDT_STATUS_NMI;
+            self.count = self.timeout;
+            self.signal = Some(Signal::Nmi(self.expirations));
+        } else {
+            self.ctrl = 0;
+            self.status = WDT_STATUS_RESET;
+            self.signal = Some(Signal::Reset);
+        }
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Watchdog {
+        Watchdog::new()
+    }
+}
+
+impl Device for Watchdog {
+    fn size(&self) -> u64 {
+        WDT_SIZE
+    }
+    fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+        match offset {
+            WDT_CTRL => Ok(self.ctrl),
+            WDT_TIMEOUT => Ok(self.timeout),
+            WDT_KICK => Ok(0),
+            WDT_COUNT => Ok(self.count),
+            WDT_STATUS => Ok(self.status),
+            _ => Err(Fault::BusError),
+        }
+    }
+    fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+        match offset {
+            WDT_CTRL => {
+                if val & WDT_ENABLE != 0 && self.ctrl & WDT_ENABLE == 0 {
+                    self.kick();
+                }
+                self.ctrl = val;
+            }
+            WDT_TIMEOUT => {
+                self.timeout = val;
+                self.kick();
+            }
+            WDT_KICK => {
+                if val == WDT_KICK_KEY {
+                    self.kick();
+                }
+            }
+            WDT_STATUS => self.status &= !val,
+            _ => return Err(Fault::BusError),
+        }
+        Ok(())
+    }
+    fn tick(&mut self) {
+        if self.ctrl & WDT_ENABLE == 0 {
+            return;
+        }
+        self.count = self.count.saturating_sub(1);
+        if self.count == 0 {
+            self.expire();
+        }
+    }
+    fn take_signal(&mut self) -> Option<Signal> {
+        self.signal.take()
+    }
+}
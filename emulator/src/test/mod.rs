@@ -1,2 +1,5 @@
 mod test_emulator;
-mod test_assembler;
\ No newline at end of file
+mod test_assembler;
+mod test_conditions;
+mod test_devices;
+mod test_int_ctrl;
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod test_assembler {
-    use crate::{assembler::assemble::*, emulator::{instr::instr_to_string, CoreSys}};
+    use crate::{assembler::assemble::*, emulator::{instr::instr_to_string, int_ctrl::FIRST_IRQ, CoreSys}};
 
     #[test]
     fn test_assembler_simple() {
@@ -13,7 +13,7 @@ mod test_assembler {
         hlt
         "));
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(0), 2);
         assert_eq!(sys.get_reg(1), (-1 as i64) as u64);
@@ -37,7 +37,7 @@ mod test_assembler {
         "));
         while !sys.halted() {
             println!("{}", instr_to_string(sys.get_next_instr()));
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(2), 1);
         assert_eq!(sys.get_reg(3), 1);
@@ -73,7 +73,7 @@ mod test_assembler {
         hlt
         "));
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(0), 1);
         assert_eq!(sys.get_reg(1), 1);
@@ -96,7 +96,7 @@ mod test_assembler {
         hlt
         "));
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(0), 1);
     }
@@ -110,7 +110,7 @@ mod test_assembler {
         hlt
         "));
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(2), 16);
     }
@@ -124,7 +124,7 @@ mod test_assembler {
         hlt
         "));
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(2), 1);
     }
@@ -153,7 +153,7 @@ mod test_assembler {
         b lr
         "));
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(0), 120);
     }
@@ -164,13 +164,13 @@ mod test_assembler {
         mvi r0
         hlt
         "));
-        sys = sys.set_int_table(vec![0, 0]);
+        sys = sys.set_int_table(vec![0; FIRST_IRQ as usize + 1]);
         let mut interrupted = false;
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
             if !interrupted {
                 interrupted = true;
-                sys = sys.interrupt(1, 2);
+                sys.interrupt(FIRST_IRQ, 2).unwrap();
             }
         }
         assert_eq!(sys.get_reg(0), 2);
@@ -182,14 +182,16 @@ mod test_assembler {
         b =main
         mvi r0
         main:
-        mov r1, #1
+        mov r1, #16
         cmp r0, #2
         intne r1, #2
         hlt
         "));
-        sys = sys.set_int_table(vec![0, 8]);
+        let mut table = vec![0; FIRST_IRQ as usize + 1];
+        table[FIRST_IRQ as usize] = 8;
+        sys = sys.set_int_table(table);
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(0), 2);
     }
@@ -201,7 +203,7 @@ mod test_assembler {
         hlt
         "));
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_qry(), 3);
     }
@@ -219,8 +221,60 @@ mod test_assembler {
         hlt
         "));
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(0), 11);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_sys_reg() {
+        let assembled = to_binary(&preprocess("
+        msr ptbr, r1
+        mrs r2, mmuctl
+        msr usp, r3
+        svc #4
+        ".to_string()));
+        assert_eq!(instr_to_string(assembled[0]), "msr ptbr, r1");
+        assert_eq!(instr_to_string(assembled[1]), "mrs r2, mmuctl");
+        assert_eq!(instr_to_string(assembled[2]), "msr usp, r3");
+        assert_eq!(instr_to_string(assembled[3]), "svc #4");
+    }
+    #[test]
+    #[should_panic(expected = "System register outside msr or mrs: ptbr")]
+    fn test_sys_reg_outside_msr() {
+        to_binary(&preprocess("mov r0, ptbr".to_string()));
+    }
+    #[test]
+    #[should_panic(expected = "Unknown system register: r2")]
+    fn test_msr_needs_sys_reg() {
+        to_binary(&preprocess("msr r2, r1".to_string()));
+    }
+    #[test]
+    fn test_vectors() {
+        let assembled = to_binary(&preprocess("
+        .vectors
+        0, handler, 3
+        handler:
+        eret
+        ".to_string()));
+        assert_eq!(assembled[..3], [0, 24, 3]);
+        assert_eq!(instr_to_string(assembled[3]), "eret");
+    }
+    #[test]
+    #[should_panic(expected = "Missing entries after .vectors")]
+    fn test_trailing_vectors() {
+        to_binary(&preprocess("nop\n.vectors".to_string()));
+    }
+    #[test]
+    fn test_ports() {
+        let assembled = to_binary(&preprocess("
+        in r2, #3
+        out #4, r1
+        ineq r1, r5
+        int r1, #2
+        ".to_string()));
+        assert_eq!(instr_to_string(assembled[0]), "in r2, #3");
+        assert_eq!(instr_to_string(assembled[1]), "out #4, r1");
+        assert_eq!(instr_to_string(assembled[2]), "ineq r1, r5");
+        assert_eq!(instr_to_string(assembled[3]), "int r1, #2");
+    }
+}
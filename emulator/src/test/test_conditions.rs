@@ -0,0 +1,149 @@
+#[cfg(test)]
+mod test_conditions {
+    use crate::assembler::assemble::assemble;
+    use crate::emulator::{fault::Fault, instr::{instr_to_string, Operation}, reg_file::{RegFile, LR, PC, PSR_U, SP}, CoreSys};
+
+    // everything the guest could observe except pc, memory last
+    fn state(sys: &CoreSys) -> Vec<u64> {
+        let mut ret = sys.dump_common_regs();
+        ret[PC] = 0;
+        ret.extend(vec![
+            sys.dump_cpsr() as u64,
+            sys.get_user_mode() as u64,
+            sys.get_irq_masked() as u64,
+            sys.get_elr(),
+            sys.get_spsr(),
+            sys.get_vbar(),
+            sys.get_irq_pending(),
+            sys.get_int_data(),
+            sys.get_qry(),
+        ]);
+        ret.extend(sys.dump_mem().iter().map(|byte| *byte as u64));
+        ret
+    }
+
+    // loads instr at address 8 and erets to it with the status set to psr
+    // every register holds something by then
+    fn load_one(instr: u64, psr: u64) -> CoreSys {
+        let mut sys = CoreSys::new();
+        let mut mem = assemble(&format!("
+        b =main
+        nop
+        hlt
+        main:
+        mov r1, #11
+        mov r2, #2048
+        mov r3, #0
+        mov r4, #44
+        mov r5, #55
+        mov r6, #66
+        mov r7, #77
+        mov lr, #88
+        mov r0, #{}
+        msr spsr, r0
+        mov r0, #8
+        msr elr, r0
+        mov r0, #3
+        eret
+        ", psr));
+        mem[8..16].copy_from_slice(&instr.to_be_bytes());
+        sys = sys.load_mem(mem);
+        while sys.get_reg(PC as u64) != 8 {
+            sys = sys.step().unwrap();
+        }
+        sys
+    }
+
+    // runs instr with the flags set to nzcv
+    // returns the state before and after it and the next pc
+    fn run_one(instr: u64, nzcv: u64) -> (Vec<u64>, Vec<u64>, u64) {
+        let mut sys = load_one(instr, nzcv);
+        let before = state(&sys);
+        sys = sys.step().unwrap();
+        (before, state(&sys), sys.get_reg(PC as u64))
+    }
+
+    // rd r1, ra r2, rb r3, c #4
+    fn encode(op: u64, cond: u64) -> u64 {
+        cond << 60 | 1 << 58 | op << 44 | 1 << 40 | 2 << 36 | 3 << 32 | 4
+    }
+
+    #[test]
+    fn test_failed_condition_changes_nothing() {
+        let ops: Vec<u64> = (0..1 << 14)
+            .filter(|op| Operation::try_new(*op).is_ok())
+            .collect();
+        // al always passes and 0b1111 is undefined
+        for cond in 0..0b1110 {
+            let nzcv = (0..16)
+                .find(|nzcv| !RegFile::new().set_cpsr(*nzcv).get_cond(cond))
+                .unwrap() as u64;
+            for op in ops.iter() {
+                let instr = encode(*op, cond);
+                let (before, after, pc) = run_one(instr, nzcv);
+                assert!(before == after, "{} changed the state", instr_to_string(instr));
+                assert_eq!(pc, 16, "{}", instr_to_string(instr));
+            }
+        }
+    }
+    #[test]
+    fn test_failed_condition_in_user_mode() {
+        let ops: Vec<u64> = (0..1 << 14)
+            .filter(|op| Operation::try_new(*op).map(|op| op.privileged()).unwrap_or(false))
+            .collect();
+        for cond in 0..0b1110 {
+            let nzcv = (0..16)
+                .find(|nzcv| !RegFile::new().set_cpsr(*nzcv).get_cond(cond))
+                .unwrap() as u64;
+            for op in ops.iter() {
+                let instr = encode(*op, cond);
+                let sys = load_one(instr, nzcv | PSR_U).step().unwrap();
+                assert_eq!(sys.get_reg(PC as u64), 16, "{}", instr_to_string(instr));
+            }
+        }
+        // taken they still fault
+        for op in ops.iter() {
+            let err = load_one(encode(*op, 0b1110), PSR_U).step().err().unwrap();
+            assert_eq!(err.fault(), Fault::PrivilegeViolation);
+        }
+    }
+    #[test]
+    fn test_conditional_bl_and_push() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r1, #5
+        cmp r1, #5
+        hltne
+        blne =skipped
+        pushne r1
+        popne r2
+        mov r3, lr
+        mov r4, sp
+        bleq =taken
+        pusheq r1
+        popeq r5
+        push sp
+        pop r8
+        hlt
+        skipped:
+        mov r6, #1
+        hlt
+        taken:
+        mov r7, lr
+        mov pc, lr
+        "));
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(3), 0);
+        assert_eq!(sys.get_reg(4), 4096);
+        assert_eq!(sys.get_reg(2), 0);
+        assert_eq!(sys.get_reg(6), 0);
+        // the taken forms still link and use the stack
+        assert_eq!(sys.get_reg(7), 72);
+        assert_eq!(sys.get_reg(LR as u64), 72);
+        assert_eq!(sys.get_reg(5), 5);
+        assert_eq!(sys.get_reg(8), 4096);
+        assert_eq!(sys.get_reg(SP as u64), 4096);
+    }
+}
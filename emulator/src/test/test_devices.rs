@@ -0,0 +1,1040 @@
+#[cfg(test)]
+mod test_devices {
+    use crate::assembler::assemble::{assemble, to_memory};
+    use crate::emulator::{audio::*, block::*, device::{AttachError, Device, Signal}, dma::*, fault::{Exception, Fault}, framebuffer::{Framebuffer, FB_MAX_PIXELS}, gpio::*, int_ctrl::FIRST_IRQ, keyboard::*, nic::*, reg_file::PC, rng::Rng, rtc::*, text_display::{TextDisplay, TEXT_MAX_CELLS}, timer::*, uart::*, watchdog::*, CoreSys};
+
+    // counts cycles, raises an interrupt once the count reaches the limit
+    struct Counter {
+        count: u64,
+        limit: u64,
+    }
+
+    impl Device for Counter {
+        fn size(&self) -> u64 {
+            16
+        }
+        fn read(&mut self, offset: u64) -> Result<u64, Fault> {
+            match offset {
+                0 => Ok(self.count),
+                8 => Ok(self.limit),
+                _ => Err(Fault::BusError),
+            }
+        }
+        fn write(&mut self, offset: u64, val: u64) -> Result<(), Fault> {
+            match offset {
+                0 => self.count = val,
+                8 => self.limit = val,
+                _ => return Err(Fault::BusError),
+            }
+            Ok(())
+        }
+        fn tick(&mut self) {
+            self.count += 1;
+        }
+        fn query(&mut self, val: u64) -> Option<u64> {
+            Some(val * 2)
+        }
+        fn take_interrupt(&mut self) -> Option<(u64, u64)> {
+            if self.count == self.limit {
+                Some((FIRST_IRQ, self.count))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_mmio_device() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #8192
+        mov r1, #100
+        str r1, r0, r0, #0
+        ldr r2, r0
+        ldr r3, r0
+        add r4, r0, #8
+        ldr r5, r4
+        hlt
+        "));
+        sys.attach(8192, Box::new(Counter { count: 0, limit: 0 })).unwrap();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(2), 101);
+        assert_eq!(sys.get_reg(3), 102);
+        assert_eq!(sys.get_reg(5), 0);
+        assert_eq!(sys.device::<Counter>().unwrap().count, 106);
+    }
+    #[test]
+    fn test_device_interrupt_and_query() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        handler:
+        mvi r4
+        qry #21
+        mvi r1
+        hlt
+        main:
+        b =main
+        "));
+        let mut table = vec![0; FIRST_IRQ as usize + 1];
+        table[FIRST_IRQ as usize] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach(8192, Box::new(Counter { count: 0, limit: 5 })).unwrap();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(4), 5);
+        assert_eq!(sys.get_reg(1), 42);
+        // registers outside the device window are a bus error
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #8208
+        ldr r1, r0
+        "));
+        sys.attach(8192, Box::new(Counter { count: 0, limit: 0 })).unwrap();
+        sys = sys.step().unwrap();
+        assert_eq!(sys.step().err().unwrap().fault(), Fault::BusError);
+        // registers have to be word aligned, the machine keeps running without the device
+        let mut sys = CoreSys::new();
+        assert_eq!(sys.attach(8700, Box::new(Counter { count: 0, limit: 0 })), Err(AttachError::Unaligned));
+        assert_eq!(sys.attach_timer(8196, 16), Err(AttachError::Unaligned));
+        assert!(sys.device::<Counter>().is_none());
+        assert!(sys.step().is_ok());
+    }
+    #[test]
+    fn test_timer() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        handler:
+        add r4, r4, #1
+        eret
+        main:
+        mov r0, #8192
+        mov r1, #10
+        add r2, r0, #16
+        str r1, r2
+        mov r1, #7
+        str r1, r0
+        loop:
+        b =loop
+        "));
+        let mut table = vec![0; 17];
+        table[16] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach_timer(8192, 16).unwrap();
+        let mut entered = Vec::new();
+        for cycle in 1..=40 {
+            sys = sys.step().unwrap();
+            if sys.get_reg(PC as u64) == 8 {
+                entered.push(cycle);
+            }
+        }
+        // enabled on cycle 7, then fires every 10 cycles
+        assert_eq!(entered, vec![17, 27, 37]);
+        assert_eq!(sys.get_reg(4), 3);
+        assert_eq!(sys.device::<Timer>().unwrap().get_status(), 1);
+    }
+    #[test]
+    fn test_one_shot_timer() {
+        let mut timer = Timer::new(20);
+        timer.write(TIMER_RELOAD, 2).unwrap();
+        timer.write(TIMER_COMPARE, 5).unwrap();
+        timer.write(TIMER_CTRL, TIMER_ENABLE | TIMER_IRQ_ENABLE).unwrap();
+        let mut fired = Vec::new();
+        for cycle in 1..=10 {
+            timer.tick();
+            if let Some(int) = timer.take_interrupt() {
+                fired.push((cycle, int));
+            }
+        }
+        assert_eq!(fired, vec![(3, (20, 5))]);
+        assert_eq!(timer.read(TIMER_CTRL).unwrap(), TIMER_IRQ_ENABLE);
+        assert_eq!(timer.get_count(), 5);
+        timer.write(TIMER_STATUS, 1).unwrap();
+        assert_eq!(timer.get_status(), 0);
+        assert_eq!(timer.read(TIMER_SIZE).err(), Some(Fault::BusError));
+    }
+    #[test]
+    fn test_uart_console() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        echo:
+        ldr r1, r2
+        and r1, r1, #1
+        cmp r1, #0
+        beq =echo_done
+        ldr r1, r0
+        add r1, r1, #1
+        str r1, r0
+        b =echo
+        echo_done:
+        eret
+        main:
+        mov r0, #8192
+        add r2, r0, #8
+        add r3, r0, #16
+        mov r1, #104
+        str r1, r0
+        mov r1, #105
+        str r1, r0
+        mov r1, #1
+        str r1, r3
+        loop:
+        b =loop
+        "));
+        let mut table = vec![0; 18];
+        table[17] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach_uart(8192, 17).unwrap();
+        for _ in 0..20 {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.console_take_output(), b"hi".to_vec());
+        assert_eq!(sys.console_take_output(), Vec::<u8>::new());
+        // the rx interrupt handler echoes every byte shifted by one
+        sys.console_write_input(b"abc");
+        for _ in 0..100 {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.console_take_output(), b"bcd".to_vec());
+        assert_eq!(sys.device::<Uart>().unwrap().pending_input(), 0);
+    }
+    #[test]
+    fn test_framebuffer() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        vsync:
+        mvi r4
+        eret
+        main:
+        mov r0, #8192
+        mov r1, #255
+        lsl r1, r1, #16
+        mov r2, #8456
+        str r1, r2
+        mov r2, #4096
+        lsl r2, r2, #48
+        mov r3, #1
+        lsl r3, r3, #32
+        orr r2, r2, r3
+        mov r3, #10496
+        str r2, r3
+        mov r1, #5
+        add r2, r0, #32
+        str r1, r2
+        mov r1, #1
+        str r1, r0
+        loop:
+        b =loop
+        "));
+        let mut table = vec![0; 19];
+        table[18] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach_framebuffer(8192, 4, 2, 4, 18).unwrap();
+        for _ in 0..40 {
+            sys = sys.step().unwrap();
+        }
+        let mut golden = b"P6\n4 2\n255\n".to_vec();
+        golden.extend_from_slice(&[255, 0, 0]);
+        golden.extend_from_slice(&[0; 18]);
+        golden.extend_from_slice(&[255, 0, 0]);
+        assert_eq!(sys.framebuffer_ppm().unwrap(), golden);
+        let rgba = sys.framebuffer_rgba();
+        assert_eq!(rgba.len(), 32);
+        assert_eq!(rgba[..8], [255, 0, 0, 255, 0, 0, 0, 255]);
+        // vsync every 5 cycles from cycle 16, the last one is not taken yet
+        let frame = sys.device::<Framebuffer>().unwrap().get_frame();
+        assert_eq!(frame, 5);
+        assert_eq!(sys.get_reg(4), 4);
+    }
+    #[test]
+    fn test_framebuffer_config() {
+        let mut sys = CoreSys::new();
+        let mut attach = |width, height, bpp| sys.attach_framebuffer(8192, width, height, bpp, 18).err();
+        assert_eq!(attach(4, 2, 3), Some(AttachError::UnsupportedBpp));
+        assert_eq!(attach(0, 2, 4), Some(AttachError::Empty));
+        assert_eq!(attach(u64::MAX, 2, 4), Some(AttachError::TooLarge));
+        assert_eq!(attach(FB_MAX_PIXELS, 2, 1), Some(AttachError::TooLarge));
+        assert_eq!(attach(FB_MAX_PIXELS, 1, 8), None);
+        // the machine survives the failed attempts with only the good framebuffer
+        assert_eq!(sys.device::<Framebuffer>().unwrap().get_width(), FB_MAX_PIXELS);
+    }
+    #[test]
+    fn test_text_display() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        msg:
+        .asciz
+        hello
+        main:
+        mov r0, =msg
+        ldr r1, r0
+        mov r2, #8544
+        loop:
+        cmp r1, #0
+        beq =done
+        add r0, r0, #8
+        ldr r3, r0
+        orr r3, r3, #7936
+        str r3, r2
+        add r2, r2, #8
+        sub r1, r1, #1
+        b =loop
+        done:
+        mov r2, #8192
+        mov r3, #7
+        add r4, r2, #16
+        str r3, r4
+        mov r3, #1
+        add r4, r2, #24
+        str r3, r4
+        hlt
+        "));
+        sys.attach_text_display(8192, 10, 3).unwrap();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_text_screen(), "\n  hello\n");
+        let attrs = sys.get_text_attrs();
+        assert_eq!(attrs.len(), 30);
+        assert_eq!(attrs[11..18], [0, 0x1f, 0x1f, 0x1f, 0x1f, 0x1f, 0]);
+        assert_eq!(sys.get_text_cursor(), vec![7, 1]);
+        assert_eq!(sys.device::<TextDisplay>().unwrap().cell(2, 1), ('h', 0x1f));
+    }
+    #[test]
+    fn test_text_display_config() {
+        let mut sys = CoreSys::new();
+        let mut attach = |cols, rows| sys.attach_text_display(8192, cols, rows).err();
+        assert_eq!(attach(0, 25), Some(AttachError::Empty));
+        assert_eq!(attach(80, 0), Some(AttachError::Empty));
+        assert_eq!(attach(u64::MAX, 2), Some(AttachError::TooLarge));
+        assert_eq!(attach(TEXT_MAX_CELLS + 1, 1), Some(AttachError::TooLarge));
+        assert_eq!(attach(1, 1), None);
+        assert_eq!(sys.get_text_cursor(), vec![0, 0]);
+    }
+    #[test]
+    fn test_keyboard() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        handler:
+        ldr r1, r2
+        and r1, r1, #1
+        cmp r1, #0
+        beq =drained
+        ldr r6, r0
+        add r5, r5, #1
+        b =handler
+        drained:
+        eret
+        main:
+        mov r0, #8192
+        add r2, r0, #8
+        mov r1, #1
+        add r3, r0, #16
+        str r1, r3
+        loop:
+        b =loop
+        "));
+        let mut table = vec![0; 20];
+        table[19] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach_keyboard(8192, 19).unwrap();
+        for _ in 0..10 {
+            sys = sys.step().unwrap();
+        }
+        // both events survive until the guest drains the queue
+        sys.key_event(65, true, MOD_SHIFT);
+        sys.key_event(65, false, MOD_SHIFT);
+        for _ in 0..40 {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(5), 2);
+        assert_eq!(sys.get_reg(6), 0x141);
+        assert_eq!(sys.device::<Keyboard>().unwrap().pending(), 0);
+
+        let mut keyboard = Keyboard::new(19);
+        for code in 0..=KBD_QUEUE_SIZE as u8 {
+            keyboard.push(key_event(code, true, 0));
+        }
+        assert_eq!(keyboard.read(KBD_COUNT).unwrap(), KBD_QUEUE_SIZE as u64);
+        assert_eq!(keyboard.read(KBD_STATUS).unwrap(), KBD_READY | KBD_OVERFLOW);
+        // the irq is still off
+        assert_eq!(keyboard.take_interrupt(), None);
+        keyboard.write(KBD_STATUS, KBD_OVERFLOW).unwrap();
+        assert_eq!(keyboard.read(KBD_STATUS).unwrap(), KBD_READY);
+        assert_eq!(keyboard.read(KBD_DATA).unwrap(), KEY_PRESSED);
+    }
+    #[test]
+    fn test_block_device() {
+        let program = "
+        b =main
+        write_done:
+        mvi r7
+        hlt
+        main:
+        mov r0, #8192
+        mov r1, #1
+        mov r2, #8208
+        str r1, r2
+        str r1, r0
+        wait:
+        mov r2, #8200
+        ldr r3, r2
+        and r3, r3, #2
+        cmp r3, #0
+        beq =wait
+        mov r2, #8704
+        ldr r5, r2
+        add r5, r5, #1
+        str r5, r2
+        mov r1, #2
+        mov r2, #8208
+        str r1, r2
+        mov r1, #1
+        mov r2, #8224
+        str r1, r2
+        mov r2, #8200
+        mov r1, #2
+        str r1, r2
+        str r1, r0
+        idle:
+        b =idle
+        ";
+        let mut image = vec![0; 1100];
+        image[512..520].copy_from_slice(&42u64.to_be_bytes());
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble(program));
+        let mut table = vec![0; 21];
+        table[20] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach_block_device(8192, 20, image).unwrap();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(5), 43);
+        assert_eq!(sys.get_reg(7), BLK_DONE);
+        let image = sys.get_block_image();
+        assert_eq!(image.len(), 3 * SECTOR_SIZE as usize);
+        assert_eq!(image[1024..1032], 43u64.to_be_bytes());
+
+        // the same program against an image file
+        let path = std::env::temp_dir().join(format!("emulator-disk-{}.img", std::process::id()));
+        std::fs::write(&path, &image).unwrap();
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble(program));
+        let mut table = vec![0; 21];
+        table[20] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach_disk_image(8192, 20, &path).unwrap();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        sys.save_disk_image(&path).unwrap();
+        let saved = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved[1024..1032], 43u64.to_be_bytes());
+        // a missing image or a bad base leaves the machine usable
+        assert!(sys.attach_disk_image(8192, 20, &path).is_err());
+        std::fs::write(&path, [0; 512]).unwrap();
+        assert!(sys.attach_disk_image(8700, 20, &path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(sys.halted());
+        // the buffer window only takes whole words inside it
+        let mut disk = BlockDevice::new(20, vec![]);
+        assert_eq!(disk.read(BLK_BUFFER + 4), Err(Fault::UnalignedAccess));
+        assert_eq!(disk.write(BLK_SIZE, 1), Err(Fault::BusError));
+        assert_eq!(disk.read(BLK_SIZE - 8), Ok(0));
+        // reading past the last sector is an error
+        let mut disk = BlockDevice::new(20, vec![]);
+        disk.write(BLK_CMD, BLK_CMD_READ).unwrap();
+        for _ in 0..BLK_LATENCY {
+            disk.tick();
+        }
+        assert_eq!(disk.read(BLK_STATUS).unwrap(), BLK_ERROR);
+    }
+    #[test]
+    fn test_dma() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        data:
+        .word
+        1
+        .word
+        2
+        .word
+        3
+        .word
+        4
+        dma_done:
+        mvi r7
+        hlt
+        main:
+        mov r0, #12288
+        mov r1, =data
+        str r1, r0
+        mov r1, #8704
+        add r2, r0, #8
+        str r1, r2
+        mov r1, #32
+        add r2, r0, #16
+        str r1, r2
+        mov r1, #3
+        add r2, r0, #24
+        str r1, r2
+        idle:
+        b =idle
+        "));
+        let mut table = vec![0; 22];
+        table[21] = 40;
+        sys = sys.set_int_table(table);
+        sys.attach_block_device(8192, 20, vec![0; 512]).unwrap();
+        sys.attach_dma(12288, 21).unwrap();
+        let mut owners = Vec::new();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+            owners.push(sys.get_bus_owner());
+        }
+        // one word per cycle while the cpu keeps going
+        assert_eq!(owners.iter().filter(|owner| **owner == 2).count(), 4);
+        assert_eq!(sys.get_reg(7), DMA_DONE);
+        assert_eq!(sys.get_dma_state(), vec![40, 8736, 0, 2, DMA_DONE]);
+        let disk = sys.device::<BlockDevice>().unwrap();
+        let buffer: Vec<u64> = (0..5).map(|i| disk.read(BLK_BUFFER + i * 8).unwrap()).collect();
+        assert_eq!(buffer, vec![1, 2, 3, 4, 0]);
+
+        // a bus error stops the copy at the failing word
+        let mut sys = CoreSys::new();
+        sys.attach_dma(12288, 21).unwrap();
+        let dma = sys.device::<Dma>().unwrap();
+        dma.write(DMA_SRC, 4080).unwrap();
+        dma.write(DMA_DST, 0).unwrap();
+        dma.write(DMA_LEN, 32).unwrap();
+        dma.write(DMA_CTRL, DMA_START).unwrap();
+        assert_eq!(dma.write(DMA_SRC, 0), Err(Fault::BusError));
+        for _ in 0..4 {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_dma_state(), vec![4096, 16, 16, 0, DMA_ERROR]);
+    }
+    #[test]
+    fn test_rtc() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        alarm:
+        mvi r6
+        hlt
+        main:
+        mov r0, #8192
+        add r1, r0, #8
+        ldr r2, r1
+        mov r5, #10
+        spin:
+        sub r5, r5, #1
+        cmp r5, #0
+        bne =spin
+        ldr r3, r1
+        sub r3, r3, r2
+        qry #256
+        mvi r4
+        ; qry writes r0 as well
+        mov r0, #8192
+        mov r7, #60
+        add r1, r0, #16
+        str r7, r1
+        mov r7, #1
+        add r1, r0, #24
+        str r7, r1
+        idle:
+        b =idle
+        "));
+        let mut table = vec![0; 23];
+        table[22] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach_rtc(8192, 22, 1_700_000_000).unwrap();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        // the spin loop plus the ldr that reads the end count
+        assert_eq!(sys.get_reg(3), 32);
+        assert_eq!(sys.get_reg(4), 1_700_000_000);
+        assert_eq!(sys.get_reg(6), 60);
+        sys.set_rtc_time(42);
+        assert_eq!(sys.device::<Rtc>().unwrap().time(), 42);
+
+        let mut rtc = Rtc::new(22, 5);
+        for _ in 0..RTC_CYCLES_PER_SECOND * 3 / 2 {
+            rtc.tick();
+        }
+        assert_eq!(rtc.read(RTC_TIME).unwrap(), 6);
+        assert_eq!(rtc.query(RTC_QUERY_CYCLES), Some(RTC_CYCLES_PER_SECOND * 3 / 2));
+        assert_eq!(rtc.take_interrupt(), None);
+        // the seconds wrap around instead of overflowing
+        rtc.write(RTC_TIME, u64::MAX).unwrap();
+        for _ in 0..RTC_CYCLES_PER_SECOND {
+            rtc.tick();
+        }
+        assert_eq!(rtc.read(RTC_TIME).unwrap(), 0);
+    }
+    #[test]
+    fn test_rng() {
+        let program = "
+        mov r0, #8192
+        ldr r1, r0
+        ldr r2, r0
+        add r4, r0, #8
+        ldr r5, r4
+        str r5, r4
+        ldr r3, r0
+        hlt
+        ";
+        let run = |seed| {
+            let mut sys = CoreSys::new();
+            sys = sys.load_mem(assemble(program));
+            sys.attach_rng(8192, seed).unwrap();
+            while !sys.halted() {
+                sys = sys.step().unwrap();
+            }
+            (sys.get_reg(1), sys.get_reg(2), sys.get_reg(3), sys.get_reg(5))
+        };
+        let (first, second, again, seed) = run(1234);
+        assert_ne!(first, second);
+        // writing the seed back restarts the sequence
+        assert_eq!(again, first);
+        assert_eq!(seed, 1234);
+        assert_eq!(run(1234), (first, second, again, seed));
+        assert_ne!(run(1235).0, first);
+        // 0 is a usable seed
+        let mut rng = Rng::new(0);
+        assert_ne!(rng.next_word(), rng.next_word());
+        let mut entropy = Rng::from_entropy();
+        assert_ne!(entropy.next_word(), entropy.next_word());
+    }
+    #[test]
+    fn test_gpio() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        digits:
+        .word
+        63
+        .word
+        6
+        .word
+        91
+        press:
+        add r4, r4, #1
+        str r4, r0
+        mov r1, =digits
+        ldr r2, r1, r4, #8
+        add r3, r0, #8
+        str r2, r3
+        add r3, r0, #40
+        mov r2, #1
+        str r2, r3
+        eret
+        main:
+        mov r0, #8192
+        add r1, r0, #24
+        mov r2, #1
+        str r2, r1
+        idle:
+        b =idle
+        "));
+        let mut table = vec![0; 25];
+        table[24] = 32;
+        sys = sys.set_int_table(table);
+        sys.attach_gpio(8192, 24).unwrap();
+        let mut run = |mut sys: CoreSys, steps| {
+            for _ in 0..steps {
+                sys = sys.step().unwrap();
+            }
+            sys
+        };
+        sys = run(sys, 10);
+        // two presses of the button on pin 0, releases do not interrupt
+        sys.set_gpio_input(0, true);
+        sys = run(sys, 20);
+        sys.set_gpio_input(0, false);
+        sys = run(sys, 20);
+        sys.set_gpio_input(0, true);
+        sys = run(sys, 20);
+        assert_eq!(sys.get_gpio_state(), vec![2, 91, 1, 0]);
+        let digits = sys.get_seven_segment();
+        assert_eq!(digits.len(), 8);
+        assert_eq!(seg_to_hex(digits[0]), Some(2));
+        // blank digits show nothing
+        assert_eq!(seg_to_hex(digits[1]), None);
+        assert_eq!(seg_to_hex(SEG_HEX[0xb] | SEG_DP), Some(0xb));
+        assert_eq!(sys.device::<Gpio>().unwrap().leds(), 2);
+    }
+    #[test]
+    fn test_gpio_pin_out_of_range() {
+        let mut gpio = Gpio::new(FIRST_IRQ);
+        gpio.set_input(GPIO_PINS - 1, true);
+        gpio.set_input(GPIO_PINS, true);
+        gpio.set_input(u64::MAX, true);
+        assert_eq!(gpio.dump(), vec![0, 0, 1 << 63, 0]);
+    }
+    #[test]
+    fn test_audio() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #8192
+        mov r1, #1000
+        add r2, r0, #16
+        str r1, r2
+        mov r1, #128
+        add r2, r0, #24
+        str r1, r2
+        mov r1, #255
+        add r2, r0, #32
+        str r1, r2
+        loop:
+        b =loop
+        "));
+        sys.attach_audio(8192).unwrap();
+        for _ in 0..AUDIO_CYCLES_PER_SAMPLE * 16 {
+            sys = sys.step().unwrap();
+        }
+        // a 1 khz square wave at half duty is 4 samples high then 4 low
+        let wave = [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0];
+        assert_eq!(sys.take_audio_samples(), [wave, wave].concat());
+        assert_eq!(sys.take_audio_samples(), Vec::<f32>::new());
+        for _ in 0..AUDIO_CYCLES_PER_SAMPLE * 4 {
+            sys = sys.step().unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("emulator-audio-{}.wav", std::process::id()));
+        sys.save_audio_wav(&path).unwrap();
+        let saved = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.len(), 44 + 4 * 2);
+        assert_eq!(saved[..4], *b"RIFF");
+        assert_eq!(saved[44..46], 32767i16.to_le_bytes());
+
+        // pcm samples are mixed in one per sample period
+        let mut audio = Audio::new();
+        audio.write(AUDIO_PCM, 16384).unwrap();
+        audio.write(AUDIO_PCM, -16384i64 as u64).unwrap();
+        assert_eq!(audio.read(AUDIO_FIFO).unwrap(), 2);
+        for _ in 0..AUDIO_CYCLES_PER_SAMPLE * 3 {
+            audio.tick();
+        }
+        assert_eq!(audio.take_samples(), vec![0.5, -0.5, 0.0]);
+
+        // tones above nyquist are clamped instead of wrapping around to a low note
+        let mut audio = Audio::new();
+        audio.write(AUDIO_TONE + AUDIO_TONE_FREQ, AUDIO_SAMPLE_RATE + 1).unwrap();
+        assert_eq!(audio.read(AUDIO_TONE + AUDIO_TONE_FREQ).unwrap(), AUDIO_MAX_FREQ);
+        audio.write(AUDIO_TONE + AUDIO_TONE_FREQ, u64::MAX).unwrap();
+        assert_eq!(audio.read(AUDIO_TONE + AUDIO_TONE_FREQ).unwrap(), AUDIO_MAX_FREQ);
+    }
+    #[test]
+    fn test_nic_ping() {
+        let mut client = CoreSys::new();
+        client = client.load_mem(assemble("
+        b =main
+        rx:
+        mov r0, #8960
+        add r1, r0, #16
+        ldr r5, r1
+        add r1, r0, #24
+        ldr r6, r1
+        hlt
+        main:
+        mov r0, #8448
+        mov r1, #2
+        str r1, r0
+        add r2, r0, #16
+        mov r1, #1
+        str r1, r2
+        add r2, r0, #24
+        mov r1, #7
+        str r1, r2
+        mov r0, #8192
+        mov r1, #1
+        str r1, r0
+        add r2, r0, #24
+        mov r1, #4
+        str r1, r2
+        add r2, r0, #40
+        mov r1, #1
+        str r1, r2
+        idle:
+        b =idle
+        "));
+        // answers every request with a reply carrying the same sequence number
+        let mut server = CoreSys::new();
+        server = server.load_mem(assemble("
+        b =main
+        rx:
+        mov r0, #8960
+        add r1, r0, #8
+        ldr r2, r1
+        add r1, r0, #24
+        ldr r3, r1
+        mov r0, #8448
+        str r2, r0
+        add r1, r0, #16
+        mov r4, #2
+        str r4, r1
+        add r1, r0, #24
+        str r3, r1
+        mov r0, #8192
+        add r1, r0, #24
+        mov r4, #4
+        str r4, r1
+        add r1, r0, #40
+        mov r4, #3
+        str r4, r1
+        add r7, r7, #1
+        eret
+        main:
+        mov r0, #8192
+        mov r1, #1
+        str r1, r0
+        idle:
+        b =idle
+        "));
+        let mut table = vec![0; 26];
+        table[25] = 8;
+        client = client.set_int_table(table.clone());
+        client.attach_nic(8192, 1, 25).unwrap();
+        server = server.set_int_table(table);
+        server.attach_nic(8192, 2, 25).unwrap();
+        let mut switch = Switch::new();
+        let client = switch.connect(client);
+        let server = switch.connect(server);
+        let mut steps = 0;
+        while !switch.machine(client).halted() {
+            switch.step().unwrap();
+            steps += 1;
+            assert!(steps < 200);
+        }
+        assert_eq!(switch.port_reg(client, 5), 2);
+        assert_eq!(switch.port_reg(client, 6), 7);
+        assert_eq!(switch.port_reg(server, 7), 1);
+        assert!(!switch.port_halted(server));
+        // nothing is plugged into the port
+        assert!(switch.port_halted(2));
+        assert_eq!(switch.port_reg(2, 5), 0);
+        // the same programs always take the same number of steps
+        assert_eq!(steps, 48);
+
+        let mut nic = Nic::new(1, 25);
+        nic.write(NIC_TX_LEN, 1).unwrap();
+        nic.write(NIC_CMD, NIC_CMD_SEND).unwrap();
+        assert_eq!(nic.read(NIC_STATUS).unwrap(), NIC_TX_ERROR);
+        assert!(nic.take_sent().is_empty());
+
+        // a faulting machine does not stop the others from stepping or receiving
+        let mut sender = CoreSys::new();
+        sender = sender.load_mem(assemble("
+        mov r0, #8448
+        mov r1, #2
+        str r1, r0
+        mov r0, #8192
+        add r2, r0, #24
+        str r1, r2
+        add r2, r0, #40
+        mov r1, #1
+        str r1, r2
+        hlt
+        "));
+        sender.attach_nic(8192, 1, 25).unwrap();
+        let mut receiver = CoreSys::new();
+        receiver = receiver.load_mem(to_memory(vec![!0]));
+        receiver.attach_nic(8192, 2, 25).unwrap();
+        let mut switch = Switch::new();
+        let sender = switch.connect(sender);
+        let receiver = switch.connect(receiver);
+        let mut faults = 0;
+        while !switch.machine(sender).halted() {
+            if let Err(err) = switch.step() {
+                assert_eq!(err.ports(), vec![receiver]);
+                faults += 1;
+            }
+        }
+        assert!(faults > 1);
+        assert_eq!(switch.port_fault(sender), None);
+        assert_eq!(switch.port_fault(receiver), Some(Fault::UndefinedInstruction));
+        let nic = switch.machine(receiver).device::<Nic>().unwrap();
+        assert_eq!(nic.read(NIC_RX_LEN).unwrap(), 2);
+    }
+    #[test]
+    fn test_watchdog() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        nmi:
+        mov r7, #1
+        mov r1, #3000
+        str r7, r1
+        eret
+        main:
+        mov r0, #8192
+        add r1, r0, #32
+        ldr r2, r1
+        cmp r2, #2
+        beq =rebooted
+        mov r7, #20
+        add r1, r0, #8
+        str r7, r1
+        mov r7, #3
+        str r7, r0
+        mov r5, #5
+        kick:
+        mov r7, #23294
+        add r1, r0, #16
+        str r7, r1
+        sub r5, r5, #1
+        cmp r5, #0
+        bne =kick
+        hang:
+        b =hang
+        rebooted:
+        mov r1, #3000
+        ldr r3, r1
+        hlt
+        "));
+        let mut table = vec![0; 9];
+        table[8] = 8;
+        sys = sys.set_int_table(table);
+        sys.attach_watchdog(8192).unwrap();
+        let mut steps = 0;
+        while !sys.halted() && steps < 1000 {
+            sys = sys.step().unwrap();
+            steps += 1;
+        }
+        assert!(sys.halted());
+        // the warning ran the nmi handler, the second expiry rebooted
+        assert_eq!(sys.get_reg(3), 1);
+        assert_eq!(sys.get_reset_count(), 1);
+        assert_eq!(sys.get_watchdog_state(), vec![0, 20, 0, WDT_STATUS_RESET, 2]);
+
+        let mut watchdog = Watchdog::new();
+        watchdog.write(WDT_TIMEOUT, 3).unwrap();
+        watchdog.write(WDT_CTRL, WDT_ENABLE).unwrap();
+        watchdog.tick();
+        watchdog.write(WDT_KICK, 1).unwrap();
+        assert_eq!(watchdog.read(WDT_COUNT).unwrap(), 2);
+        watchdog.write(WDT_KICK, WDT_KICK_KEY).unwrap();
+        assert_eq!(watchdog.read(WDT_COUNT).unwrap(), 3);
+        watchdog.tick();
+        watchdog.tick();
+        assert_eq!(watchdog.take_signal(), None);
+        watchdog.tick();
+        assert_eq!(watchdog.take_signal(), Some(Signal::Reset));
+        assert_eq!(watchdog.read(WDT_CTRL).unwrap(), 0);
+    }
+    // answers with the sum of everything written to it
+    struct Summer {
+        sum: u64,
+    }
+    impl Device for Summer {
+        fn ports(&self) -> u64 {
+            1
+        }
+        fn port_in(&mut self, _port: u64) -> Result<u64, Fault> {
+            Ok(self.sum)
+        }
+        fn port_out(&mut self, _port: u64, val: u64) -> Result<(), Fault> {
+            self.sum += val;
+            Ok(())
+        }
+    }
+    #[test]
+    fn test_ports() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        bad_port:
+        mvi r6
+        hlt
+        main:
+        mov r1, #104
+        out #16, r1
+        mov r1, #105
+        out #16, r1
+        poll:
+        in r1, #17
+        and r1, r1, #1
+        cmp r1, #0
+        beq =poll
+        in r2, #16
+        mov r3, #40
+        out #64, r3
+        mov r3, #2
+        out #64, r3
+        in r4, #64
+        in r5, #99
+        hlt
+        "));
+        let mut table = vec![0; 4];
+        table[Exception::MemoryFault as usize] = 8;
+        sys = sys.set_int_table(table);
+        // the uart registers are ports 16 to 18 as well as mmio, whatever is attached after it
+        sys.attach_uart(8192, 17).unwrap();
+        sys.attach_timer(12288, 18).unwrap();
+        sys = sys.map_ports(8192, 16);
+        sys = sys.attach_ports(64, Box::new(Summer { sum: 0 }));
+        for _ in 0..20 {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.console_take_output(), b"hi".to_vec());
+        assert!(!sys.halted());
+        sys.console_write_input(b"x");
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(2), 120);
+        assert_eq!(sys.get_reg(4), 42);
+        // nothing answers port 99, the memory fault handler gets the port
+        assert_eq!(sys.get_reg(5), 0);
+        assert_eq!(sys.get_reg(6), 99);
+    }
+    #[test]
+    fn test_host_ports() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        bad_port:
+        mvi r6
+        hlt
+        main:
+        in r1, #33
+        add r1, r1, #1
+        out #32, r1
+        out #33, r1
+        in r2, #34
+        hlt
+        "));
+        let mut table = vec![0; 4];
+        table[Exception::MemoryFault as usize] = 8;
+        sys = sys.set_int_table(table);
+        assert_eq!(sys.attach_host_ports(32, 0), Err(AttachError::Empty));
+        sys.attach_host_ports(32, 2).unwrap();
+        sys.set_host_port(33, 41);
+        // outside the range, nothing to set
+        sys.set_host_port(34, 7);
+        for _ in 0..5 {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.take_host_port_writes(), vec![32, 42, 33, 42]);
+        assert!(sys.take_host_port_writes().is_empty());
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        // nothing answers port 34, the memory fault handler gets the port
+        assert_eq!(sys.get_reg(2), 0);
+        assert_eq!(sys.get_reg(6), 34);
+    }
+}
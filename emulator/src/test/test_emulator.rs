@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod test_emulator {
-    use crate::emulator::{core_sys::MEM_SIZE, instr::instr_to_string, reg_file::PC, CoreSys};
+    use crate::assembler::assemble::assemble;
+    use crate::emulator::{core_sys::MEM_SIZE, fault::{Exception, Fault}, int_ctrl::FIRST_IRQ, mem::{Mem, MemError, MAX_FLAT_SIZE, PAGE_SIZE}, mem_map::{Access, RegionKind, PERM_R, PERM_RWX, PERM_W, PERM_X}, mmu::{Mmu, MMUCTL_ENABLE, PTE_R, PTE_U, PTE_VALID, PTE_W, PTE_X}, instr::instr_to_string, reg_file::PC, CoreSys};
 
     #[test]
     fn test_one_plus_one() {
@@ -17,11 +18,11 @@ mod test_emulator {
             // add r0, r1, r2
             0b1110_0001, 0b0000_0000, 0b0001_0000, 0b0000_0001, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0010,
         ]);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(1), 1);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(2), 1);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(0), 2);
     }
 
@@ -43,14 +44,14 @@ mod test_emulator {
             // moveq r2, #1
             0b0000_0101, 0b0000_0000, 0b0000_0010, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0001,
         ]);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(0), 1);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(1), 1);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(0), 0);
         assert_eq!(sys.dump_cpsr(), 0b0100);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(2), 1);
     }
 
@@ -69,9 +70,9 @@ mod test_emulator {
             // mov r2, #1
             0b1110_0101, 0b0000_0000, 0b0000_0010, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0001,
         ]);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(15), 16);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_ne!(sys.get_reg(1), 1);
         assert_eq!(sys.get_reg(2), 1);
     }
@@ -97,11 +98,11 @@ mod test_emulator {
             // hlt
             0b1110_0000, 0b0000_0000, 0b0001_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000,
         ]);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(0), 1);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.dump_cpsr(), 0b0100);
-        sys = sys.step();
+        sys = sys.step().unwrap();
         assert_eq!(sys.get_reg(15), 32);
         assert_ne!(sys.get_reg(1), 1);
     }
@@ -143,7 +144,7 @@ mod test_emulator {
         ]);
         while !sys.halted() {
             println!("{}", instr_to_string(sys.get_next_instr()));
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(0), 21);
     }
@@ -170,7 +171,7 @@ mod test_emulator {
             0b1110_0000, 0b0000_0000, 0b0001_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000,
         ]);
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(1), 255);
     }
@@ -202,7 +203,7 @@ mod test_emulator {
         ]);
         while !sys.halted() {
             println!("{}", instr_to_string(sys.get_next_instr()));
-            sys = sys.step();
+            sys = sys.step().unwrap();
             sys.print();
         }
         assert_eq!(sys.get_reg(0), 255);
@@ -274,7 +275,7 @@ mod test_emulator {
         ]);
         while !sys.halted() {
             println!("{}", instr_to_string(sys.get_next_instr()));
-            sys = sys.step();
+            sys = sys.step().unwrap();
         }
         assert_eq!(sys.get_reg(0), 120);
     }
@@ -295,16 +296,505 @@ mod test_emulator {
             // hlt
             0b1110_0000, 0b0000_0000, 0b0001_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000,
         ]);
-        sys = sys.set_int_table(vec![0, 0]);
+        sys = sys.set_int_table(vec![0; FIRST_IRQ as usize + 1]);
         let mut first_int = false;
         while !sys.halted() {
-            sys = sys.step();
+            sys = sys.step().unwrap();
             sys.print();
             if !first_int && sys.get_reg(PC as u64) == 8 {
-                sys = sys.interrupt(1, 2);
+                sys.interrupt(FIRST_IRQ, 2).unwrap();
                 first_int = true;
             }
         }
         assert_eq!(sys.get_reg(0), 4);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_undefined_instruction() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(vec![
+            // mov r0, #1
+            0b1110_0101, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0001,
+            // op code 0b01_1111_1111_1111 does not exist
+            0b1110_0101, 0b1111_1111, 0b1111_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000, 0b0000_0000,
+        ]);
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::UndefinedInstruction);
+        assert_eq!(err.pc(), 8);
+        assert_eq!(err.instr(), 0xe5ff_f000_0000_0000);
+        let sys = err.into_core();
+        assert_eq!(sys.get_reg(PC as u64), 8);
+        assert_eq!(sys.get_reg(0), 1);
+        assert_eq!(sys.get_fault(), Some(Fault::UndefinedInstruction));
+        // the core stays faulted until the host clears it
+        assert!(sys.step().is_err());
+    }
+    #[test]
+    fn test_memory_faults() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble(&format!("
+        mov r0, #{}
+        ldr r1, r0
+        hlt
+        ", MEM_SIZE)));
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::BusError);
+        assert_eq!(err.pc(), 8);
+
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #4
+        str r0, r0
+        hlt
+        "));
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::UnalignedAccess);
+    }
+    #[test]
+    fn test_divide_by_zero_and_missing_vector() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #4
+        div r1, r0, #0
+        hlt
+        "));
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::DivideByZero);
+
+        // multiplying by zero, shifting every bit out and signed overflow are not faults
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #5
+        mul r1, r0, #0
+        lsl r2, r0, #64
+        lsr r3, r0, #100
+        mvn r4, #0
+        asr r5, r4, #64
+        lsl r6, r0, #0
+        ror r7, r0, #64
+        mov r8, #1
+        lsl r8, r8, #63
+        sdiv r9, r8, r4
+        smodu r10, r8, r4
+        hlt
+        "));
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(1), 0);
+        assert_eq!(sys.get_reg(2), 0);
+        assert_eq!(sys.get_reg(3), 0);
+        assert_eq!(sys.get_reg(5), u64::MAX);
+        assert_eq!(sys.get_reg(6), 5);
+        assert_eq!(sys.get_reg(7), 5);
+        // the most negative number divided by -1 wraps
+        assert_eq!(sys.get_reg(9), 1 << 63);
+        assert_eq!(sys.get_reg(10), 0);
+
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #16
+        int r0, #0
+        hlt
+        "));
+        sys = sys.step().unwrap();
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::MissingVector);
+        assert_eq!(err.pc(), 16);
+    }
+    #[test]
+    fn test_exception_vectors() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        div_zero:
+        mov r2, #7
+        mrs r3, elr
+        add r3, r3, #8
+        msr elr, r3
+        eret
+        main:
+        mov lr, #3
+        mov r0, #4
+        div r1, r0, #0
+        hlt
+        "));
+        let mut table = vec![0; 8];
+        table[Exception::DivideByZero as usize] = 8;
+        sys = sys.set_int_table(table);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(2), 7);
+        assert_eq!(sys.get_elr(), 72);
+        assert_eq!(sys.get_reg(14), 3);
+        assert_eq!(sys.get_fault(), None);
+    }
+    #[test]
+    fn test_guest_vector_table() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        vectors:
+        .vectors
+        0, div_zero, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, tick
+        div_zero:
+        mov r2, #7
+        mrs r3, elr
+        add r3, r3, #8
+        msr elr, r3
+        eret
+        tick:
+        mvi r4
+        eret
+        main:
+        mov r0, =vectors
+        msr vbar, r0
+        div r1, r0, #0
+        mov r5, #16
+        int r5, #9
+        hlt
+        "));
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_vbar(), 8);
+        assert_eq!(sys.get_reg(2), 7);
+        assert_eq!(sys.get_reg(4), 9);
+        // an empty entry is a missing vector even with a host table installed
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #512
+        msr vbar, r0
+        div r1, r0, #0
+        "));
+        sys = sys.set_int_table(vec![0, 0]);
+        sys = sys.step().unwrap();
+        sys = sys.step().unwrap();
+        assert_eq!(sys.step().err().unwrap().fault(), Fault::DivideByZero);
+    }
+    #[test]
+    fn test_user_mode() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        vectors:
+        .vectors
+        0, 0, 0, 0, 0, priv_fault, 0, sys_call
+        sys_call:
+        mvi r4
+        mrs r6, spsr
+        eret
+        priv_fault:
+        mrs r5, elr
+        mov r7, sp
+        hlt
+        user:
+        mov r1, sp
+        svc #3
+        cpsid
+        hlt
+        main:
+        mov r0, =vectors
+        msr vbar, r0
+        mov r0, #2048
+        msr usp, r0
+        mov r0, =user
+        msr elr, r0
+        mov r0, #16
+        msr spsr, r0
+        eret
+        "));
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        // user code runs on its own stack and reaches the kernel through svc
+        assert_eq!(sys.get_reg(1), 2048);
+        assert_eq!(sys.get_reg(4), 3);
+        assert_eq!(sys.get_reg(6), 16);
+        // cpsid faults in user mode
+        assert_eq!(sys.get_reg(5), 136);
+        assert_eq!(sys.get_reg(7), MEM_SIZE as u64);
+        assert!(!sys.get_user_mode());
+    }
+    #[test]
+    fn test_memory_fault_handler() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble(&format!("
+        b =main
+        mem_fault:
+        mvi r3
+        mrs r4, elr
+        hlt
+        main:
+        mov r0, #{}
+        str r0, r0
+        hlt
+        ", MEM_SIZE + 64)));
+        let mut table = vec![0; 8];
+        table[Exception::MemoryFault as usize] = 8;
+        sys = sys.set_int_table(table);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(3), MEM_SIZE as u64 + 64);
+        assert_eq!(sys.get_reg(4), 40);
+        // no handler for undefined instructions, so it still reaches the host
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(vec![0xff; 8]);
+        sys = sys.set_int_table(vec![0, 0]);
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::UndefinedInstruction);
+    }
+    #[test]
+    fn test_with_memory() {
+        let mut sys = CoreSys::with_memory(64 * 1024).unwrap();
+        assert_eq!(sys.get_mem_size(), 64 * 1024);
+        sys = sys.load_mem(assemble("
+        mov r0, #8000
+        str r0, r0
+        ldr r1, r0
+        push r1
+        hlt
+        "));
+        assert_eq!(sys.get_reg(13), 64 * 1024);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(1), 8000);
+        assert_eq!(sys.get_reg(13), 64 * 1024 - 8);
+    }
+    #[test]
+    fn test_sparse_memory() {
+        let size = 1 << 40;
+        let mut sys = CoreSys::with_sparse_memory(size).unwrap();
+        sys = sys.load_mem(assemble("
+        mov r0, #1
+        push r0
+        ldr r1, sp
+        hlt
+        "));
+        assert_eq!(sys.get_mem_size(), size);
+        assert_eq!(sys.get_mem_pages(), 1);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(1), 1);
+        // the stack touched one more page at the top of the address space
+        assert_eq!(sys.get_mem_pages(), 2);
+        assert_eq!(sys.dump_mem_range(size - 8, 16), vec![0, 0, 0, 0, 0, 0, 0, 1]);
+        // only the touched pages are dumped, never the whole space
+        assert!(sys.dump_mem().is_empty());
+        let pages = sys.dump_mem_pages();
+        assert_eq!(pages.len() as u64, 2 * (8 + PAGE_SIZE));
+        assert_eq!(pages[..8], 0u64.to_be_bytes());
+        assert_eq!(pages[8 + PAGE_SIZE as usize..][..8], (size / PAGE_SIZE - 1).to_be_bytes());
+        assert_eq!(pages[pages.len() - 1], 1);
+    }
+    #[test]
+    fn test_memory_size_errors() {
+        assert_eq!(CoreSys::with_memory(0).err(), Some(MemError::Empty));
+        assert_eq!(CoreSys::with_memory(12).err(), Some(MemError::Unaligned));
+        assert_eq!(CoreSys::with_memory(MAX_FLAT_SIZE + 8).err(), Some(MemError::TooLarge));
+        assert_eq!(CoreSys::with_sparse_memory(0).err(), Some(MemError::Empty));
+        assert_eq!(CoreSys::with_sparse_memory(u64::MAX).err(), Some(MemError::Unaligned));
+        assert!(CoreSys::with_sparse_memory(MAX_FLAT_SIZE * 64).is_ok());
+    }
+    #[test]
+    fn test_memory_map() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #8
+        str r0, r0
+        hlt
+        "));
+        sys = sys.map_region(0, 256, RegionKind::Rom, PERM_RWX);
+        sys = sys.map_region(256, 256, RegionKind::Ram, PERM_R | PERM_W);
+        assert_eq!(sys.dump_regions(), vec![
+            0, MEM_SIZE as u64, RegionKind::Ram as u64, PERM_RWX as u64,
+            0, 256, RegionKind::Rom as u64, (PERM_R | PERM_X) as u64,
+            256, 256, RegionKind::Ram as u64, (PERM_R | PERM_W) as u64,
+        ]);
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::AccessViolation);
+        assert_eq!(err.addr(), 8);
+
+        // jumping into the data region is not allowed either
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b #256
+        "));
+        sys = sys.map_region(256, 256, RegionKind::Ram, PERM_R | PERM_W);
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::AccessViolation);
+        assert_eq!(err.pc(), 256);
+
+        // mmio without a device behind it is a bus error
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #512
+        str r0, r0
+        hlt
+        "));
+        sys = sys.map_region(512, 8, RegionKind::Mmio, PERM_R | PERM_W);
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::BusError);
+    }
+    fn put_word(mem: &mut [u8], addr: usize, val: u64) {
+        mem[addr..addr + 8].copy_from_slice(&val.to_be_bytes());
+    }
+
+    // identity map the code page, map va 0x1000 to pa 0x2000 and va 0x3000 read only
+    fn paged_image(program: &str) -> Vec<u8> {
+        let mut image = vec![0; 0x8000];
+        let code = assemble(program);
+        image[..code.len()].copy_from_slice(&code);
+        put_word(&mut image, 0x4000, 0x5000 | PTE_VALID);
+        put_word(&mut image, 0x5000, 0x6000 | PTE_VALID);
+        put_word(&mut image, 0x6000, PTE_VALID | PTE_R | PTE_X);
+        put_word(&mut image, 0x6000 + 8, 0x2000 | PTE_VALID | PTE_R | PTE_W);
+        put_word(&mut image, 0x6000 + 3 * 8, 0x7000 | PTE_VALID | PTE_R);
+        image
+    }
+
+    #[test]
+    fn test_mmu() {
+        let mut sys = CoreSys::with_memory(0x8000).unwrap();
+        sys = sys.load_mem(paged_image("
+        mov r0, #16384
+        msr ptbr, r0
+        mov r0, #1
+        msr mmuctl, r0
+        mov r0, #4096
+        mov r1, #42
+        str r1, r0
+        ldr r2, r0
+        mrs r3, ptbr
+        hlt
+        "));
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(2), 42);
+        assert_eq!(sys.get_reg(3), 0x4000);
+        assert_eq!(sys.dump_mem_range(0x2000, 8), vec![0, 0, 0, 0, 0, 0, 0, 42]);
+        assert_eq!(sys.dump_mem_range(0x1000, 8), vec![0; 8]);
+        // one miss for the code page and one for the data page
+        assert_eq!(sys.get_tlb_misses(), 2);
+        assert_eq!(sys.get_tlb_hits(), 6);
+    }
+    #[test]
+    fn test_page_fault() {
+        let mut sys = CoreSys::with_memory(0x8000).unwrap();
+        sys = sys.load_mem(paged_image("
+        b =main
+        page_fault:
+        mvi r2
+        hlt
+        main:
+        mov r0, #12288
+        ldr r1, r0
+        str r1, r0
+        hlt
+        "));
+        sys = sys.enable_mmu(0x4000);
+        let mut table = vec![0; 8];
+        table[Exception::PageFault as usize] = 8;
+        sys = sys.set_int_table(table);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(2), 0x3000);
+        assert_eq!(sys.get_elr(), 40);
+
+        // unmapped pages reach the host when there is no handler
+        let mut sys = CoreSys::with_memory(0x8000).unwrap();
+        sys = sys.load_mem(paged_image("
+        mov r0, #8192
+        ldr r1, r0
+        "));
+        sys = sys.enable_mmu(0x4000);
+        sys = sys.step().unwrap();
+        let err = sys.step().err().unwrap();
+        assert_eq!(err.fault(), Fault::PageFault);
+        assert_eq!(err.addr(), 0x2000);
+
+        // a table pointer at the top of memory faults instead of wrapping
+        let mut mmu = Mmu::new();
+        mmu.set_ptbr(u64::MAX - 7);
+        mmu.set_ctl(MMUCTL_ENABLE);
+        let mem = Mem::flat(0x8000).unwrap();
+        assert_eq!(mmu.translate(&mem, 1 << 30, Access::Read, false), Err(Fault::PageFault));
+    }
+    #[test]
+    fn test_user_pages() {
+        // the code page and va 0x3000 are user pages, va 0x1000 is kernel only
+        let mut image = paged_image("
+        mov r0, #16
+        msr spsr, r0
+        mov r0, =user
+        msr elr, r0
+        eret
+        user:
+        mov r0, #12288
+        ldr r1, r0
+        mov r0, #4096
+        ldr r2, r0
+        hlt
+        ");
+        put_word(&mut image, 0x6000, PTE_VALID | PTE_R | PTE_X | PTE_U);
+        put_word(&mut image, 0x6000 + 3 * 8, 0x7000 | PTE_VALID | PTE_R | PTE_U);
+        put_word(&mut image, 0x7000, 7);
+        let mut sys = CoreSys::with_memory(0x8000).unwrap();
+        sys = sys.load_mem(image);
+        sys = sys.enable_mmu(0x4000);
+        let err = loop {
+            match sys.step() {
+                Ok(next) => sys = next,
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.fault(), Fault::PageFault);
+        assert_eq!(err.addr(), 0x1000);
+        let sys = err.into_core();
+        assert!(sys.get_user_mode());
+        assert_eq!(sys.get_reg(1), 7);
+    }
+    #[test]
+    fn test_tlb_flush() {
+        // the leaf table is mapped at its own address so the guest can edit it
+        let mut image = paged_image("
+        mov r0, #4096
+        ldr r1, r0
+        mov r3, #24584
+        mov r4, #28675
+        str r4, r3
+        ldr r2, r0
+        msr tlbflush, r0
+        ldr r5, r0
+        mrs r6, tlbflush
+        hlt
+        ");
+        put_word(&mut image, 0x6000 + 6 * 8, 0x6000 | PTE_VALID | PTE_R | PTE_W);
+        put_word(&mut image, 0x2000, 1);
+        put_word(&mut image, 0x7000, 2);
+        let mut sys = CoreSys::with_memory(0x8000).unwrap();
+        sys = sys.load_mem(image);
+        sys = sys.enable_mmu(0x4000);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(1), 1);
+        // the stale translation is used until the flush
+        assert_eq!(sys.get_reg(2), 1);
+        assert_eq!(sys.get_reg(5), 2);
+        assert_eq!(sys.get_reg(6), 0);
+    }
+}
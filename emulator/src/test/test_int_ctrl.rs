@@ -0,0 +1,186 @@
+#[cfg(test)]
+mod test_int_ctrl {
+    use crate::assembler::assemble::assemble;
+    use crate::emulator::device::AttachError;
+    use crate::emulator::fault::Fault;
+    use crate::emulator::int_ctrl::*;
+    use crate::emulator::CoreSys;
+
+    #[test]
+    fn test_pending_and_priority() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        handler:
+        mvi r1
+        mul r3, r3, #16
+        add r3, r3, r1
+        add r4, r4, #1
+        cmp r4, #2
+        beq =done
+        eret
+        main:
+        b =main
+        done:
+        hlt
+        "));
+        let mut table = vec![0; FIRST_IRQ as usize + 2];
+        table[FIRST_IRQ as usize] = 8;
+        table[FIRST_IRQ as usize + 1] = 8;
+        sys = sys.set_int_table(table);
+        sys = sys.set_irq_priority(FIRST_IRQ, 5);
+        // both are raised before the core gets to run, neither is lost
+        sys.interrupt(FIRST_IRQ, 1).unwrap();
+        sys.interrupt(FIRST_IRQ + 1, 2).unwrap();
+        assert_eq!(sys.get_irq_pending(), 0b11 << FIRST_IRQ);
+        assert_eq!(sys.get_int(), FIRST_IRQ + 1);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(3), 0x21);
+        assert_eq!(sys.get_irq_pending(), 0);
+        assert!(sys.get_irq_masked());
+    }
+    #[test]
+    fn test_masking() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        handler:
+        mvi r1
+        hlt
+        main:
+        cpsid
+        mov r0, #1
+        mov r0, #2
+        cpsie
+        mov r0, #3
+        hlt
+        "));
+        let mut table = vec![0; FIRST_IRQ as usize + 1];
+        table[FIRST_IRQ as usize] = 8;
+        sys = sys.set_int_table(table.clone());
+        sys = sys.step().unwrap();
+        sys = sys.step().unwrap();
+        assert!(sys.get_irq_masked());
+        sys.interrupt(FIRST_IRQ, 7).unwrap();
+        sys = sys.step().unwrap();
+        sys = sys.step().unwrap();
+        assert_eq!(sys.get_reg(0), 2);
+        assert_eq!(sys.get_irq_pending(), 1 << FIRST_IRQ);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(0), 2);
+        assert_eq!(sys.get_reg(1), 7);
+        // a disabled line stays pending and is never taken
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #1
+        hlt
+        "));
+        sys = sys.set_int_table(table);
+        sys = sys.set_irq_enabled(FIRST_IRQ, false);
+        sys.interrupt(FIRST_IRQ, 7).unwrap();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(0), 1);
+        assert_eq!(sys.get_irq_pending(), 1 << FIRST_IRQ);
+        assert_eq!(sys.get_irq_enabled(), !(1 << FIRST_IRQ));
+    }
+    #[test]
+    fn test_eret_restores_context() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        b =main
+        handler:
+        mrs r2, spsr
+        mov r3, #1
+        cmp r3, #2
+        eret
+        main:
+        mov lr, #99
+        cmp r0, #0
+        beq =taken
+        hlt
+        taken:
+        mov r1, #1
+        hlt
+        "));
+        let mut table = vec![0; FIRST_IRQ as usize + 1];
+        table[FIRST_IRQ as usize] = 8;
+        sys = sys.set_int_table(table);
+        for _ in 0..3 {
+            sys = sys.step().unwrap();
+        }
+        // lands between the cmp and the beq
+        sys.interrupt(FIRST_IRQ, 0).unwrap();
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(1), 1);
+        assert_eq!(sys.get_reg(2), 0b0100);
+        assert_eq!(sys.get_reg(14), 99);
+        assert_eq!(sys.get_elr(), 56);
+        assert!(!sys.get_irq_masked());
+    }
+    #[test]
+    fn test_exception_vectors_are_not_irqs() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble("
+        mov r0, #1
+        mov r1, #1
+        int r1, #2
+        hlt
+        "));
+        sys = sys.set_int_table(vec![8; FIRST_IRQ as usize]);
+        // lines below FIRST_IRQ belong to exceptions and are refused
+        for line in (0..FIRST_IRQ).chain(IRQ_LINES..IRQ_LINES + 1) {
+            assert_eq!(sys.interrupt(line, 0), Err(Fault::ReservedIrq));
+        }
+        assert_eq!(sys.get_irq_pending(), 0);
+        assert_eq!(sys.attach_timer(8192, 1), Err(AttachError::ReservedIrq));
+        assert_eq!(sys.attach_uart(8192, IRQ_LINES), Err(AttachError::ReservedIrq));
+        let err = loop {
+            match sys.step() {
+                Ok(next) => sys = next,
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.fault(), Fault::ReservedIrq);
+        assert_eq!(err.pc(), 16);
+        assert_eq!(err.into_core().get_reg(0), 1);
+    }
+    #[test]
+    fn test_mmio_registers() {
+        let mut sys = CoreSys::new();
+        sys = sys.load_mem(assemble(&format!("
+        cpsid
+        mov r0, #8192
+        add r7, r0, #{}
+        mov r1, #19
+        str r1, r7
+        mov r1, #21
+        str r1, r7
+        ldr r2, r0
+        add r7, r0, #{}
+        ldr r3, r7
+        add r7, r0, #{}
+        ldr r4, r7
+        ldr r5, r7
+        ldr r6, r0
+        hlt
+        ", INT_CTRL_RAISE, INT_CTRL_PRIORITY + 19 * 8, INT_CTRL_CLAIM)));
+        sys = sys.map_int_ctrl(8192);
+        sys = sys.set_irq_priority(19, 9);
+        while !sys.halted() {
+            sys = sys.step().unwrap();
+        }
+        assert_eq!(sys.get_reg(2), 1 << 21 | 1 << 19);
+        assert_eq!(sys.get_reg(3), 9);
+        assert_eq!(sys.get_reg(4), 21);
+        assert_eq!(sys.get_reg(5), 19);
+        assert_eq!(sys.get_reg(6), 0);
+    }
+}